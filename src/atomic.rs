@@ -0,0 +1,163 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Files are written to a temporary file next to the target, which is then
+// renamed to the target. A crash or a full disk leaves the old file intact.
+
+// Number of backups kept of stored boards and dictionaries
+pub const DEFAULT_BACKUPS: usize = 3;
+
+pub fn default_backups() -> usize {
+    DEFAULT_BACKUPS
+}
+
+// Number of temporary files created by this process
+static TEMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// Temporary file names are unique, so that writers in different processes
+// or threads don't write to the same temporary file
+fn temp_filename(fname: &str) -> String {
+    format!("{}.{}.{}.tmp", fname, process::id(),
+            TEMP_COUNT.fetch_add(1, Ordering::Relaxed))
+}
+
+// Name of the n'th backup of a file, where 1 is the most recent one
+pub fn backup_filename(fname: &str, n: usize) -> String {
+    format!("{}.bak{}", fname, n)
+}
+
+// Shift the backups of a file one step, and make the current file the most
+// recent backup. The oldest backup is dropped.
+pub fn rotate_backups(fname: &str, backups: usize) -> Result<(), String> {
+    if backups == 0 || !Path::new(fname).exists() {
+        return Ok(());
+    }
+
+    for n in (1..backups).rev() {
+        let older = backup_filename(fname, n);
+
+        if Path::new(&older).exists() {
+            fs::rename(&older, backup_filename(fname, n + 1))
+                .map_err(|e| format!("Unable to rotate backup {}: {}",
+                                     older, e))?;
+        }
+    }
+
+    fs::copy(fname, backup_filename(fname, 1))
+        .map_err(|e| format!("Unable to back up {}: {}", fname, e))?;
+
+    return Ok(());
+}
+
+pub fn write_file<D: AsRef<[u8]>>(fname: &str, data: D, backups: usize)
+                                  -> Result<(), String> {
+    let tmpname = temp_filename(fname);
+    let write = || -> std::io::Result<()> {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmpname)?;
+        file.write_all(data.as_ref())?;
+        file.sync_all()
+    };
+
+    if let Err(e) = write() {
+        let _ = fs::remove_file(&tmpname);
+        return Err(format!("Unable to write {}: {}", fname, e));
+    }
+
+    if let Err(e) = rotate_backups(fname, backups) {
+        let _ = fs::remove_file(&tmpname);
+        return Err(e);
+    }
+
+    fs::rename(&tmpname, fname)
+        .map_err(|e| format!("Unable to write {}: {}", fname, e))?;
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Empty directory for the files of a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("kryss-atomic-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        return dir;
+    }
+
+    fn read(fname: &str) -> String {
+        fs::read_to_string(fname).unwrap()
+    }
+
+    #[test]
+    fn writes_and_replaces_file() {
+        let dir = test_dir("replace");
+        let fname = dir.join("dict.json").to_string_lossy().to_string();
+
+        write_file(&fname, "one", 0).unwrap();
+        write_file(&fname, "two", 0).unwrap();
+
+        assert_eq!(read(&fname), "two");
+        assert!(!Path::new(&backup_filename(&fname, 1)).exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_backups() {
+        let dir = test_dir("rotate");
+        let fname = dir.join("dict.json").to_string_lossy().to_string();
+
+        for data in ["one", "two", "three", "four"] {
+            write_file(&fname, data, 2).unwrap();
+        }
+
+        assert_eq!(read(&fname), "four");
+        assert_eq!(read(&backup_filename(&fname, 1)), "three");
+        assert_eq!(read(&backup_filename(&fname, 2)), "two");
+        assert!(!Path::new(&backup_filename(&fname, 3)).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_file_has_no_backup() {
+        let dir = test_dir("missing");
+        let fname = dir.join("dict.json").to_string_lossy().to_string();
+
+        rotate_backups(&fname, 3).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write_keeps_no_temporary_file() {
+        let dir = test_dir("failed");
+        let fname = dir.join("missing").join("dict.json")
+            .to_string_lossy().to_string();
+
+        let e = write_file(&fname, "one", 1).unwrap_err();
+
+        assert!(e.starts_with("Unable to write"));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn temporary_file_names_are_unique() {
+        assert_ne!(temp_filename("dict.json"), temp_filename("dict.json"));
+    }
+}
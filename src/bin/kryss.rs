@@ -2,15 +2,226 @@ extern crate kryss;
 
 use kryss::Dictionary;
 use kryss::Board;
-use kryss::{KryssApp, KryssKeywordExpander};
+use kryss::{KryssApp, KryssKeywordExpander, PrintObserver};
+use kryss::Server;
+use kryss::{has_newer_recovery, recovery_filename};
 
 use cmdui::CmdUI;
 use std::env;
+use std::process;
+use std::io::stdin;
+use std::rc::Rc;
+use std::path::Path;
+
+// Offer to restore a recovery snapshot which is newer than the file. With
+// --restore, the snapshot is restored without asking. Scripts and the
+// server don't read answers from stdin, and stop rather than overwrite the
+// snapshot.
+fn restore_recovery(fname: &str, interactive: bool, restore: bool) -> bool {
+    if !has_newer_recovery(fname) {
+        return false;
+    }
+
+    if restore {
+        println!("Restoring unsaved changes to {}", fname);
+        return true;
+    }
+
+    if !interactive {
+        println!("Found unsaved changes to {}. Run with --restore to restore \
+                  them, or remove {}.", fname, recovery_filename(fname));
+        process::exit(1);
+    }
+
+    println!("Found unsaved changes to {}. Restore them? (Y/n)", fname);
+
+    let mut buf = String::new();
+    stdin().read_line(&mut buf).unwrap();
+
+    return !buf.trim().to_lowercase().starts_with('n');
+}
+
+// Read the dictionary, or its recovery snapshot. If the file is corrupt,
+// offer to recover the readable entries. Returns the dictionary, and
+// whether it was restored from the snapshot.
+fn load_dictionary(dname: &str, interactive: bool, restore: bool)
+                   -> (Dictionary, bool) {
+    let restored = restore_recovery(dname, interactive, restore);
+    let result = if restored {
+        Dictionary::from_recovery(dname)
+    }
+    else {
+        Dictionary::from_file_or_new(dname)
+    };
+
+    let e = match result {
+        Ok(dict) => {
+            if !Path::new(dname).exists() {
+                println!("No dictionary {}. Starting with an empty one.",
+                         dname);
+            }
+
+            return (dict, restored);
+        },
+        Err(e) => e,
+    };
+
+    println!("{}", e);
+
+    if !Dictionary::is_recoverable(dname) || !interactive {
+        process::exit(1);
+    }
+
+    println!("Recover the readable entries? (y/N)");
+
+    let mut buf = String::new();
+    stdin().read_line(&mut buf).unwrap();
+
+    if !buf.trim().to_lowercase().starts_with('y') {
+        process::exit(1);
+    }
+
+    match Dictionary::recover_from_file(dname) {
+        Ok((dict, count)) => {
+            println!("Recovered {} keys. The corrupt file is kept as a \
+                      backup when the dictionary is stored.", count);
+            (dict, false)
+        },
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        },
+    }
+}
+
+// Merge dictionary files into a new file, reporting conflicting entries
+fn dict_merge(args: &[String]) -> Result<(), String> {
+    let mut files = vec!();
+    let mut opt_out = None;
+    let mut i = 0;
+
+    while i < args.len() {
+        if args[i] == "-o" || args[i] == "--output" {
+            opt_out = args.get(i + 1);
+            i += 2;
+        }
+        else {
+            files.push(&args[i]);
+            i += 1;
+        }
+    }
+
+    let out = opt_out.ok_or("Missing output file (-o <filename>)")?;
+
+    if files.len() < 2 {
+        return Err("Expected at least two dictionary files".to_string());
+    }
+
+    let mut dict = Dictionary::from_file(files[0])?;
+    dict.read_all()?;
+
+    for fname in &files[1..] {
+        let mut other = Dictionary::from_file(fname)?;
+        other.read_all()?;
+
+        for conflict in dict.merge(&other) {
+            println!("{}: {}", fname, conflict);
+        }
+    }
+
+    dict.write_to_file(Some(out))?;
+
+    return Ok(());
+}
+
+// Show the words which differ between two dictionary files
+fn dict_diff(args: &[String]) -> Result<(), String> {
+    if args.len() != 2 {
+        return Err("Expected two dictionary files".to_string());
+    }
+
+    let mut dict = Dictionary::from_file(&args[0])?;
+    let mut other = Dictionary::from_file(&args[1])?;
+    dict.read_all()?;
+    other.read_all()?;
+
+    for d in dict.diff(&other) {
+        println!("{}", d.key);
+
+        for w in &d.removed {
+            println!("  - {}", dict.display_form(&d.key, w));
+        }
+
+        for w in &d.added {
+            println!("  + {}", other.display_form(&d.key, w));
+        }
+    }
+
+    return Ok(());
+}
+
+// Add the placed words of solved puzzle files to the dictionary. Files
+// which are not solved are skipped.
+fn dict_learn(dname: &str, args: &[String]) -> Result<(), String> {
+    if args.is_empty() {
+        return Err("Expected puzzle files".to_string());
+    }
+
+    let mut dict = Dictionary::from_file(dname)?;
+    let mut total = 0;
+
+    for fname in args {
+        let board = match Board::read_file(fname, &dict) {
+            Ok(board) => board,
+            Err(e) => {
+                println!("{}: {}", fname, e);
+                continue;
+            },
+        };
+
+        if !board.is_filled() {
+            println!("{}: not solved, skipped", fname);
+            continue;
+        }
+
+        let count = board.learn(&mut dict);
+
+        println!("{}: learned {} words", fname, count);
+        total += count;
+    }
+
+    println!("Learned {} words from {} files", total, args.len());
+
+    if dict.changed {
+        dict.write_to_file(None)?;
+    }
+
+    return Ok(());
+}
+
+// Dictionary file commands, which don't need a board
+fn dict_command(dname: &str, args: &[String]) -> Result<(), String> {
+    match args.first().map(|a| a.as_str()) {
+        Some("merge") => dict_merge(&args[1..]),
+        Some("diff") => dict_diff(&args[1..]),
+        Some("learn") => dict_learn(dname, &args[1..]),
+        _ => Err("Expected dict merge, dict diff or dict learn".to_string()),
+    }
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     let mut dname = "dict.json";
+    let mut opt_script = None;
+    let mut keep_going = false;
+    let mut json = false;
+    let mut fuzzy = false;
+    let mut opt_alphabet = None;
+    let mut restore = false;
+    let mut address = "127.0.0.1:8080";
+    let mut sessions = "sessions";
+    let mut puzzles = ".";
     let mut arg_count = 1;
 
     loop {
@@ -23,15 +234,125 @@ fn main() {
                 dname = &args[arg_count + 1];
                 arg_count += 2;
             },
+            "-s" | "--script" => {
+                opt_script = Some(&args[arg_count + 1]);
+                arg_count += 2;
+            },
+            "-k" | "--keep-going" => {
+                keep_going = true;
+                arg_count += 1;
+            },
+            "-j" | "--json" => {
+                json = true;
+                arg_count += 1;
+            },
+            "-f" | "--fuzzy" => {
+                fuzzy = true;
+                arg_count += 1;
+            },
+            "--restore" => {
+                restore = true;
+                arg_count += 1;
+            },
+            "--alphabet" => {
+                opt_alphabet = Some(&args[arg_count + 1]);
+                arg_count += 2;
+            },
+            "-a" | "--address" => {
+                address = &args[arg_count + 1];
+                arg_count += 2;
+            },
+            "--sessions" => {
+                sessions = &args[arg_count + 1];
+                arg_count += 2;
+            },
+            "--puzzles" => {
+                puzzles = &args[arg_count + 1];
+                arg_count += 2;
+            },
             _ => break,
         }
     }
 
-    let mut dict = Dictionary::from_file(dname);
-    let board = Board::from_file(&args[arg_count], &mut dict);
+    if args.get(arg_count).map(|a| a.as_str()) == Some("dict") {
+        if let Err(e) = dict_command(dname, &args[arg_count + 1..]) {
+            println!("{}", e);
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    // Scripts and the server run without a user to answer questions
+    let interactive = opt_script.is_none() &&
+        args.get(arg_count).map(|a| a.as_str()) != Some("serve");
+    let (mut dict, dict_restored) =
+        load_dictionary(dname, interactive, restore);
+    dict.autosave = true;
+    dict.fuzzy_keys = fuzzy;
+
+    // The alphabet is needed to check the placed words of the board
+    if let Some(alphabet) = opt_alphabet {
+        dict.set_alphabet(alphabet);
+    }
+
+    dict.set_observer(Some(Rc::new(PrintObserver::new())));
+
+    if args[arg_count] == "serve" {
+        let mut server = Server::new(dict, sessions, puzzles);
+        server.set_observer(Some(Rc::new(PrintObserver::new())));
+
+        if let Err(e) = server.run(address) {
+            println!("{}", e);
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    let fname = &args[arg_count];
+    let board_restored = restore_recovery(fname, interactive, restore);
+    let result = if board_restored {
+        Board::from_recovery(fname, &mut dict)
+    }
+    else if fname.ends_with(".json") {
+        Board::from_session_file(fname, &mut dict)
+    }
+    else {
+        Board::from_file(fname, &mut dict)
+    };
+
+    let board = match result {
+        Ok(board) => board,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        },
+    };
 
-    let kw_exp = KryssKeywordExpander::new(&board);
+    let kw_exp = KryssKeywordExpander::new(&board, &dict);
     let mut kryssapp = KryssApp::new(dict, board);
+    kryssapp.stop_on_error = !keep_going;
+    kryssapp.json = json;
+    kryssapp.set_autosave(true);
+    if let Err(e) = kryssapp.write_recovery() {
+        println!("{}", e);
+    }
+
+    if let Some(script) = opt_script {
+        // Run the script non-interactively. The script itself is
+        // responsible for storing the board and dictionary, and its other
+        // changes are discarded.
+        let result = kryssapp.run_script(script);
+        kryssapp.remove_recovery(board_restored, dict_restored);
+
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+
+        return;
+    }
 
     CmdUI::new(&mut kryssapp, Some(&kw_exp)).read_commands();
 }
@@ -1,14 +1,22 @@
 use crate::word::Word;
-use crate::dictionary::Dictionary;
+use crate::dictionary::{Dictionary, Source};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::read_to_string;
-use std::fs::File;
 use std::io::Write;
 use colored::Colorize;
 use std::cmp::{min, max};
-
-#[derive(PartialEq)]
+use std::fmt::{Formatter, Display};
+use std::path::Path;
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use crate::recovery::{recovery_filename, autosave_due};
+use crate::event::{BoardEvent, SharedObserver, notify};
+use crate::score::Score;
+use crate::pattern::Pattern;
+use crate::atomic::{write_file, default_backups};
+
+#[derive(PartialEq, Serialize, Deserialize)]
 pub enum State {
     Unsolved,
     Unsolvable,
@@ -16,6 +24,45 @@ pub enum State {
     Solved,
 }
 
+impl Display for State {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            State::Unsolved   => "unsolved",
+            State::Unsolvable => "unsolvable",
+            State::Ambiguous  => "ambiguous",
+            State::Solved     => "solved",
+        })
+    }
+}
+
+// Number of known words tried for each word of the solution sentence
+const SOLUTION_WORD_CANDIDATES: usize = 50;
+
+// Check that the letters of a placed word are in the alphabet
+fn check_alphabet(word: &Word, dict: &Dictionary) -> Result<(), String> {
+    if word.placed && !dict.in_alphabet(&word.candidates[0]) {
+        return Err(format!("Word {} has letters outside the alphabet",
+                           &word.to_string()));
+    }
+
+    return Ok(());
+}
+
+// Expected effect of placing a candidate word
+pub struct Suggestion {
+    pub index: usize,
+    pub word: String,
+    // Number of candidates removed from crossing words
+    pub eliminated: usize,
+    // Crossing words left with a single candidate
+    pub solved: Vec<usize>,
+    // Crossing words left without candidates
+    pub emptied: Vec<usize>,
+}
+
+// The board is serialized as a json session, which unlike the description
+// file keeps candidates, crossings and solver state.
+#[derive(Serialize, Deserialize)]
 pub struct Board {
     pub words: Vec<Word>,
     // a -> Vec<(b, ai, bi)>
@@ -26,20 +73,46 @@ pub struct Board {
     pub crossings: HashMap<usize, Vec<(usize, usize, usize)>>,
     width: usize,
     height: usize,
+    #[serde(skip_serializing, skip_deserializing)]
     pub changed: bool,
+    // Changed since the last recovery snapshot
+    #[serde(skip_serializing, skip_deserializing)]
+    pub dirty_since_snapshot: bool,
     pub state: State,
     pub filename: String,
-    pub colors: bool
+    pub colors: bool,
+    #[serde(skip_serializing, skip_deserializing)]
+    observer: SharedObserver,
+    // Number of backups kept when storing the board
+    #[serde(skip_serializing, skip_deserializing)]
+    #[serde(default = "default_backups")]
+    pub backups: usize,
+    // Write recovery snapshots periodically while words are placed
+    #[serde(skip_serializing, skip_deserializing)]
+    pub autosave: bool,
+    #[serde(skip_serializing, skip_deserializing)]
+    last_autosave: Option<Instant>,
 }
 
 impl Board {
-    pub fn from_file(fname: &str, dict: &mut Dictionary) -> Self {
+    pub fn from_file(fname: &str, dict: &mut Dictionary)
+                     -> Result<Self, String> {
+        let mut ret = Self::read_file(fname, dict)?;
+
+        ret.refresh_candidates(dict);
+        return Ok(ret);
+    }
+
+    // Read a description file without looking up candidates for the words
+    pub fn read_file(fname: &str, dict: &Dictionary) -> Result<Self, String> {
         let mut words = vec!();
         let mut width = 0;
         let mut height = 0;
         let mut prevlines = "".to_string();
+        let data = read_to_string(fname)
+            .map_err(|e| format!("Unable to read {}: {}", fname, e))?;
 
-        for line in read_to_string(fname).unwrap().lines() {
+        for line in data.lines() {
             if line.starts_with('#') {
                 continue;
             }
@@ -63,19 +136,31 @@ impl Board {
 
             if parts[0] == "S" {
                 // Solution line. The line specifies a list of words. Each
-                // word has four fields (no key, no candidates). So the number
-                // of parts is 1 + n*4
-                assert!((parts.len() - 1)%4 == 0);
+                // word has four fields (no key, no candidates), or is a list
+                // of cells with two fields per letter.
+                let mut i = 1;
+
+                while i < parts.len() {
+                    let n = Word::num_parts(&parts[i..])?;
+
+                    if i + n > parts.len() {
+                        return Err(format!("Incomplete solution word in {}",
+                                           cat));
+                    }
 
-                for i in 0..(parts.len() - 1)/4 {
-                    let w = Word::from_parts(&parts[i*4 + 1..i*4 + 5]);
+                    let w = Word::from_solution_parts(&parts[i..i + n])?;
+                    check_alphabet(&w, dict)?;
+                    width = max(width, w.xmax() + 1);
+                    height = max(height, w.ymax() + 1);
                     words.push(w);
+                    i += n;
                 }
 
                 continue;
             }
 
-            let word = Word::from_parts(&parts);
+            let word = Word::from_parts(&parts)?;
+            check_alphabet(&word, dict)?;
 
             width = max(width, word.xmax() + 1);
             height = max(height, word.ymax() + 1);
@@ -95,11 +180,17 @@ impl Board {
                 }
 
                 if word_a.is_conflicting(word_b) {
-                    panic!("Words {} and {} are conflicting",
-                           &word_a.to_string(), &word_b.to_string());
+                    return Err(format!("Words {} and {} are conflicting",
+                                       &word_a.to_string(),
+                                       &word_b.to_string()));
                 }
 
-                if word_a.is_crossing(word_b) {
+                if word_a.is_cells() || word_b.is_cells() {
+                    for (ai, bi) in word_a.shared_cells(word_b) {
+                        a_crossings.push((b, ai, bi));
+                    }
+                }
+                else if word_a.is_crossing(word_b) {
                     let xi = if word_a.x > word_b.x {
                         word_a.x - word_b.x
                     }
@@ -126,35 +217,118 @@ impl Board {
             crossings.insert(a, a_crossings);
         }
 
-        let mut ret = Self {
+        return Ok(Self {
             words: words,
             crossings: crossings,
             state: State::Unsolved,
             width: width,
             height: height,
             changed: false,
+            dirty_since_snapshot: false,
             filename: fname.to_string(),
             colors: true,
+            observer: None,
+            backups: default_backups(),
+            autosave: false,
+            last_autosave: None,
+        });
+    }
+
+    // Check if all words with keys are placed
+    pub fn is_filled(&self) -> bool {
+        self.words.iter().all(|w| w.key.is_none() || w.placed)
+    }
+
+    pub fn from_session_file(fname: &str, dict: &mut Dictionary)
+                             -> Result<Self, String> {
+        let mut ret = Self::read_session_file(fname)?;
+
+        ret.restore_candidates(dict);
+        return Ok(ret);
+    }
+
+    fn read_session_file(fname: &str) -> Result<Self, String> {
+        let data = read_to_string(fname)
+            .map_err(|e| format!("Unable to read {}: {}", fname, e))?;
+        let mut ret: Self = serde_json::from_str(&data)
+            .map_err(|e| format!("Invalid session {}: {}", fname, e))?;
+        ret.changed = false;
+
+        return Ok(ret);
+    }
+
+    // Default session file name. This is the description file name with a
+    // .json extension.
+    pub fn session_filename(&self) -> String {
+        Path::new(&self.filename).with_extension("json")
+            .to_string_lossy().to_string()
+    }
+
+    // Read the recovery snapshot of a description file. The board is marked
+    // as changed, since the snapshot has not been stored.
+    pub fn from_recovery(fname: &str, dict: &mut Dictionary)
+                         -> Result<Self, String> {
+        let mut ret = Self::read_session_file(&recovery_filename(fname))?;
+        ret.restore_candidates(dict);
+        ret.filename = fname.to_string();
+        ret.changed = true;
+
+        return Ok(ret);
+    }
+
+    pub fn set_observer(&mut self, observer: SharedObserver) {
+        self.observer = observer;
+    }
+
+    pub fn write_recovery(&mut self) -> Result<(), String> {
+        let filename = recovery_filename(&self.filename);
+        let data = serde_json::to_string(&self).unwrap();
+
+        self.last_autosave = Some(Instant::now());
+        write_file(&filename, data, 0)?;
+        self.dirty_since_snapshot = false;
+
+        return Ok(());
+    }
+
+    // Write a recovery snapshot if one is due, so that a long run of
+    // placements is not lost
+    fn autosave(&mut self) {
+        if !self.autosave || !autosave_due(self.last_autosave) {
+            return;
+        }
+
+        if let Err(e) = self.write_recovery() {
+            notify(&self.observer, BoardEvent::AutosaveFailed(e));
+        }
+    }
+
+    pub fn write_session_file(&mut self, opt_fname: Option<&str>)
+                              -> Result<(), String> {
+        let filename = match opt_fname {
+            Some(fname) => fname.to_string(),
+            None => self.session_filename(),
         };
 
-        ret.refresh_candidates(dict);
-        return ret;
+        let data = serde_json::to_string_pretty(&self).unwrap();
+
+        write_file(&filename, data, self.backups)
     }
 
-    pub fn write_to_file(&mut self, opt_fname: Option<&str>) {
+    pub fn write_to_file(&mut self, opt_fname: Option<&str>)
+                         -> Result<(), String> {
         let mut filename = self.filename.clone();
 
         if let Some(fname) = opt_fname {
             filename = fname.to_string();
         }
 
-        let mut file = File::create(&filename)
-            .expect("Cannot create file");
+        let mut data: Vec<u8> = vec!();
 
         // Write words
         for w in &self.words {
             if w.key.is_some() {
-                writeln!(file, "{}", w.to_string()).unwrap();
+                writeln!(data, "{}", w.to_string()).unwrap();
             }
         }
 
@@ -166,17 +340,36 @@ impl Board {
             }
 
             if !started_solution {
-                write!(file, "S").unwrap();
+                write!(data, "S").unwrap();
                 started_solution = true;
             }
 
-            write!(file, ",{}", sw.to_string()).unwrap();
+            write!(data, ",{}", sw.to_string()).unwrap();
         }
 
-        writeln!(file).unwrap();
+        writeln!(data).unwrap();
+
+        write_file(&filename, data, self.backups)?;
 
         self.filename = filename;
         self.changed = false;
+        self.dirty_since_snapshot = false;
+
+        return Ok(());
+    }
+
+    // Candidates of a board read from a session. Which candidates are
+    // inflections or synonyms is only known to the dictionary, so the keys
+    // are looked up again, also those of placed words, so that derived
+    // words are neither placed by solve nor learned.
+    fn restore_candidates(&mut self, dict: &mut Dictionary) {
+        for w in &self.words {
+            if let (true, Some(k)) = (w.placed, &w.key) {
+                dict.lookup(k, w.length, None);
+            }
+        }
+
+        self.refresh_candidates(dict);
     }
 
     pub fn refresh_candidates(&mut self, dict: &mut Dictionary) {
@@ -200,6 +393,12 @@ impl Board {
 
         let w = self.words[ix].clone();
 
+        notify(&self.observer, BoardEvent::Placed {
+            index: ix,
+            key: w.key.clone(),
+            word: w.candidates[0].clone(),
+        });
+
         let mut unplace = vec!();
 
         // Remove candidates from crossing words. Unplace placed words
@@ -209,22 +408,13 @@ impl Board {
 
             if xw.placed {
                 if xw.char_at(*bi) != w.char_at(*ai) {
-                    println!("Unplacing word {}", self.format_word(*b));
                     unplace.push(b.clone());
                 }
             }
             else {
-                let mut j = 0;
-
-                while j < xw.candidates.len() {
-                    if xw.candidates[j].chars().nth(*bi).unwrap() !=
-                        w.char_at(*ai) {
-                            xw.candidates.swap_remove(j);
-                            continue;
-                        }
-
-                    j += 1;
-                }
+                // Keep the order of the remaining candidates
+                let c = w.char_at(*ai);
+                xw.candidates.retain(|cw| cw.chars().nth(*bi).unwrap() == c);
             }
         }
 
@@ -233,16 +423,32 @@ impl Board {
         }
 
         self.changed = true;
+        self.dirty_since_snapshot = true;
+        self.autosave();
     }
 
     pub fn unplace(&mut self, ix: usize, dict: &mut Dictionary) {
+        let w = &self.words[ix];
+
+        if w.placed {
+            notify(&self.observer, BoardEvent::Unplaced {
+                index: ix,
+                key: w.key.clone(),
+                word: w.candidates[0].clone(),
+            });
+        }
+
         self.words[ix].unplace();
 
         self.refresh_candidates(dict);
+        self.changed = true;
+        self.dirty_since_snapshot = true;
+        self.autosave();
     }
 
     // Check each word. Place it if a single candidate is found. Repeat until
-    // no more candidates can be placed.
+    // no more candidates can be placed. Candidates derived from other
+    // entries are guesses, and are left for the user to place.
     pub fn solve_repeated(&mut self, dict: &mut Dictionary) {
         let mut done = false;
 
@@ -255,10 +461,10 @@ impl Board {
                     continue;
                 }
 
-                if w.has_one_candidate() {
+                if w.has_one_candidate() &&
+                    !self.is_derived(i, &w.candidates[0], dict) {
                     self.place(i, None, dict);
                     done = false;
-                    println!("Placing word {}", self.format_word(i));
                 }
             }
         }
@@ -280,6 +486,222 @@ impl Board {
              1 => State::Unsolved,
              _ => State::Ambiguous,
         };
+
+        // Solution words have no key and no candidates, so a board with a
+        // solution is learned when the words with keys are placed
+        if self.is_filled() && dict.learn {
+            self.learn(dict);
+        }
+    }
+
+    // Check if a candidate is not listed for the key of the word, but
+    // derived from other entries in the dictionary
+    fn is_derived(&self, a: usize, word: &str, dict: &Dictionary) -> bool {
+        self.words[a].key.as_ref()
+            .map_or(false, |k| dict.derivation(k, word).is_some())
+    }
+
+    // Add the placed words to the dictionary. Words with unknown keys, and
+    // words derived from other entries, are skipped. Returns the number of
+    // words which were not known for their keys.
+    pub fn learn(&self, dict: &mut Dictionary) -> usize {
+        let mut count = 0;
+
+        for w in &self.words {
+            if !w.placed {
+                continue;
+            }
+
+            if let Some(k) = &w.key {
+                let word = &w.candidates[0];
+
+                if k.contains("xxxx") || dict.source_rank(k, word).is_some() ||
+                    dict.derivation(k, word).is_some() {
+                        continue;
+                    }
+
+                dict.add_word(k, word, Source::Learned);
+                count += 1;
+            }
+        }
+
+        return count;
+    }
+
+    pub fn score_candidate(&self, a: usize, word: &str, dict: &Dictionary)
+                           -> Score {
+        let w = &self.words[a];
+        let mut score = Score::default();
+
+        if let Some(k) = &w.key {
+            score.rank = dict.source_rank(k, word);
+            score.choices = dict.choices(k, word);
+            score.derived = dict.derivation(k, word).is_some();
+        }
+
+        score.frequency = dict.frequency(word);
+
+        if let Some(cr) = self.crossings.get(&a) {
+            for (b, ai, bi) in cr {
+                let wb = &self.words[*b];
+
+                // Letters which the crossing word allows at the crossing
+                let letters: HashSet<char> = wb.candidates.iter()
+                    .filter_map(|cw| cw.chars().nth(*bi))
+                    .collect();
+
+                let c = word.chars().nth(*ai);
+
+                if c.map_or(false, |c| letters.contains(&c)) {
+                    score.crossings += 1;
+                }
+            }
+        }
+
+        return score;
+    }
+
+    // Candidates of a word with their scores, best first
+    pub fn ranked_candidates(&self, a: usize, dict: &Dictionary)
+                             -> Vec<(String, Score)> {
+        let mut ret: Vec<(String, Score)> = self.words[a].candidates.iter()
+            .map(|c| (c.clone(), self.score_candidate(a, c, dict)))
+            .collect();
+
+        ret.sort_by(|(_, s1), (_, s2)| s2.total().total_cmp(&s1.total()));
+
+        return ret;
+    }
+
+    // Indexes of the words of the solution sentence
+    pub fn solution_words(&self) -> Vec<usize> {
+        (0..self.words.len())
+            .filter(|a| self.words[*a].is_solution())
+            .collect()
+    }
+
+    // Known words which fit a solution word, best first. Known words are
+    // the answers to all keys and the words of imported word lists. A
+    // placed word is its only candidate. A word without known words is
+    // given by its hint, so that the rest of the sentence is still found.
+    fn solution_candidates(&self, a: usize, dict: &Dictionary)
+                           -> Vec<(String, f64)> {
+        let w = &self.words[a];
+        let hints = self.get_hints(a);
+
+        if w.placed {
+            return vec![(w.candidates[0].clone(), 0.0)];
+        }
+
+        let mut ret: Vec<(String, f64)> = match Pattern::parse(&hints) {
+            Ok(pattern) => dict.search(&pattern).into_iter()
+                .map(|(c, _)| {
+                    let score = self.score_candidate(a, &c, dict).total();
+                    (c, score)
+                })
+                .collect(),
+            Err(_) => vec!(),
+        };
+
+        if ret.is_empty() {
+            return vec![(hints, 0.0)];
+        }
+
+        ret.sort_by(|(c1, s1), (c2, s2)|
+                    s2.total_cmp(s1).then(c1.cmp(c2)));
+        ret.truncate(SOLUTION_WORD_CANDIDATES);
+
+        return ret;
+    }
+
+    // Phrases for the solution sentence, built from the known words which
+    // fit each solution word. A phrase is scored by the sum of the scores
+    // of its words, and at most max_phrases are returned, best first.
+    pub fn solution_phrases(&self, dict: &Dictionary, max_phrases: usize)
+                            -> Vec<(Vec<String>, f64)> {
+        let mut ret: Vec<(Vec<String>, f64)> = vec![(vec!(), 0.0)];
+
+        // The scores of the words are independent, so keeping the best
+        // partial phrases at each step finds the best phrases
+        for a in self.solution_words() {
+            let candidates = self.solution_candidates(a, dict);
+            let mut next = vec!();
+
+            for (phrase, score) in &ret {
+                for (c, s) in &candidates {
+                    let mut p = phrase.clone();
+                    p.push(c.clone());
+                    next.push((p, score + s));
+                }
+            }
+
+            next.sort_by(|(p1, s1), (p2, s2)|
+                         s2.total_cmp(s1).then(p1.cmp(p2)));
+            next.truncate(max_phrases);
+            ret = next;
+        }
+
+        if ret.iter().all(|(p, _)| p.is_empty()) {
+            return vec!();
+        }
+
+        return ret;
+    }
+
+    // Look one step ahead for each candidate of each ambiguous word, and
+    // find the placements which remove most candidates from the crossing
+    // words. Placements which leave a crossing word without candidates are
+    // put last. Ties are broken by the candidate score.
+    pub fn suggest(&self, dict: &Dictionary) -> Vec<Suggestion> {
+        let mut ret = vec!();
+
+        for (a, w) in self.words.iter().enumerate() {
+            if w.placed || !w.is_ambiguous() {
+                continue;
+            }
+
+            for cand in &w.candidates {
+                let mut s = Suggestion {
+                    index: a,
+                    word: cand.clone(),
+                    eliminated: 0,
+                    solved: vec!(),
+                    emptied: vec!(),
+                };
+
+                for (b, ai, bi) in &self.crossings[&a] {
+                    let wb = &self.words[*b];
+
+                    if wb.placed {
+                        continue;
+                    }
+
+                    let c = cand.chars().nth(*ai).unwrap();
+                    let left = wb.candidates.iter()
+                        .filter(|cw| cw.chars().nth(*bi).unwrap() == c)
+                        .count();
+
+                    s.eliminated += wb.candidates.len() - left;
+
+                    if left == 0 {
+                        s.emptied.push(*b);
+                    }
+                    else if left == 1 && wb.candidates.len() > 1 {
+                        s.solved.push(*b);
+                    }
+                }
+
+                ret.push((self.score_candidate(a, cand, dict).total(), s));
+            }
+        }
+
+        ret.sort_by(|(t1, s1), (t2, s2)|
+                    s1.emptied.len().cmp(&s2.emptied.len())
+                    .then(s2.solved.len().cmp(&s1.solved.len()))
+                    .then(s2.eliminated.cmp(&s1.eliminated))
+                    .then(t2.total_cmp(t1)));
+
+        return ret.into_iter().map(|(_, s)| s).collect();
     }
 
     fn highlight(&self, c: char) -> String {
@@ -313,7 +735,9 @@ impl Board {
         return String::from_iter(v);
     }
 
-    pub fn show_crossing(&self, a: usize) {
+    // Draw the word with its crossing words, followed by a list of the
+    // crossing words.
+    pub fn crossing_to_string(&self, a: usize) -> String {
         let w = &self.words[a];
 
         let mut cross_formatted = vec!();
@@ -391,34 +815,56 @@ impl Board {
             }
         }
 
-        println!("{}", String::from_iter(v));
-        println!();
+        let mut ret = String::from_iter(v);
+        ret.push_str("\n\n");
 
         for c in cross_formatted {
-            println!("{c}");
+            ret.push_str(&c);
+            ret.push('\n');
         }
+
+        return ret;
     }
 
-    pub fn info_word(&self, a: &usize) {
-        let w = &self.words[*a];
+    pub fn info_to_string(&self, a: usize, dict: &Dictionary) -> String {
+        let w = &self.words[a];
+        let mut lines = vec!();
+
+        // Show where the words came from
+        let source = |c: &str| -> String {
+            match w.key.as_ref().and_then(|k| dict.provenance(k, c)) {
+                Some(p) => format!("{} ({})", c, p),
+                None => c.to_string(),
+            }
+        };
 
-        println!("Orientation: {}, X: {}, Y: {}, Length: {}",
-                 w.o, w.x, w.y, w.length);
+        if w.is_cells() {
+            let cells: Vec<String> = w.cells.iter()
+                .map(|(x, y)| format!("({}, {})", x, y))
+                .collect();
+            lines.push(format!("Cells: {}", cells.join(" ")));
+        }
+        else {
+            lines.push(format!("Orientation: {}, X: {}, Y: {}, Length: {}",
+                               w.o, w.x, w.y, w.length));
+        }
         if let Some(k) = &w.key {
-            println!("Key: {}", k);
+            lines.push(format!("Key: {}", k));
         }
         if w.placed {
-            println!("Placed: {}", w.candidates[0]);
+            lines.push(format!("Placed: {}", source(&w.candidates[0])));
         }
         else if w.candidates.is_empty() {
-            println!("No candidates");
+            lines.push("No candidates".to_string());
         }
         else {
-            println!("Candidates:");
+            lines.push("Candidates:".to_string());
             for c in &w.candidates {
-                println!("  {}", c);
+                lines.push(format!("  {}", source(c)));
             }
         }
+
+        return lines.join("\n");
     }
 
     pub fn format_word(&self, i: usize) -> String {
@@ -470,8 +916,14 @@ impl Board {
     }
 }
 
-impl ToString for Board {
-    fn to_string(&self) -> String {
+impl Board {
+    // Render the board as a list of rows without any colors or other
+    // terminal formatting.
+    pub fn rows(&self) -> Vec<String> {
+        self.render(true).lines().map(|l| l.to_string()).collect()
+    }
+
+    fn render(&self, plain: bool) -> String {
         let width = self.width + 1;
         let height = self.height;
         let mut v = vec![" ".to_string(); width*height];
@@ -506,22 +958,30 @@ impl ToString for Board {
             }
         }
 
-        // In the end, draw solution using a different color
+        // In the end, draw solution using a different color. The cells of
+        // unplaced solution words are marked too, with the letters of the
+        // crossing words.
         for w in &self.words {
-            if !w.placed {
-                continue;
-            }
-
             if !w.is_solution() {
                 continue;
             }
 
             for (x, y, c) in w {
-                if self.colors {
-                    v[y*width + x] = c.to_string().green().to_string();
+                let letter = if w.placed {
+                    c.to_string()
+                }
+                else {
+                    v[y*width + x].clone()
+                };
+
+                if plain {
+                    v[y*width + x] = letter;
+                }
+                else if self.colors {
+                    v[y*width + x] = letter.green().to_string();
                 }
                 else {
-                    v[y*width + x] = c.to_string().bold().to_string();
+                    v[y*width + x] = letter.bold().to_string();
                 }
             }
         }
@@ -529,3 +989,9 @@ impl ToString for Board {
         format!("{}", String::from_iter(v))
     }
 }
+
+impl ToString for Board {
+    fn to_string(&self) -> String {
+        self.render(false)
+    }
+}
@@ -1,5 +1,5 @@
-use crate::{Board, State};
-use crate::Dictionary;
+use crate::{Board, State, Suggestion};
+use crate::{Dictionary, Source, Pattern};
 
 extern crate term_size;
 use cmdui::{CmdApp, KeywordExpander, CommandPart};
@@ -7,6 +7,14 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::io::stdin;
 use std::cmp::max;
+use std::fs::{read_to_string, canonicalize};
+use std::rc::Rc;
+use crate::recovery::remove_recovery;
+use crate::event::{BoardEvent, Observer};
+use crate::text::{normalize_key, normalize_word};
+use crate::json::{word_json, crossings_json, board_json};
+use crate::json::{ranked_candidates_json, provenance_json};
+use serde_json::{json, Value};
 
 const COMMAND_LIST: &'static [&'static str] = &[
     "solve",
@@ -18,24 +26,66 @@ const COMMAND_LIST: &'static [&'static str] = &[
     "crossing <key>",
     "candidates <key>",
     "solution",
+    "solution candidates",
+    "solution place <words>",
+    "suggest",
     "board",
     "info <key>",
     "place <key> <candidate>",
     "lookup <key> [<length>|<hint>]",
+    "keys like <text>",
+    "search <pattern>",
+    "anagram <letters> [<hint>]",
+    "anagram phrase <letters> [<hint>]",
     "set colors <bool>",
+    "set stoponerror <bool>",
+    "set json <bool>",
+    "set autosave <bool>",
+    "set transliterate <bool>",
+    "set alphabet <letters>",
+    "set fuzzy <bool>",
+    "set synonyms <bool>",
+    "set inflect <bool>",
+    "set learn <bool>",
+    "set backups <number>",
     "store board <filename>",
     "store dictionary <filename>",
+    "store session <filename>",
     "add <key> <word>",
+    "learn",
+    "import frequencies <filename>",
+    "dict show <key>",
+    "dict remove <key> <word>",
+    "dict forget <key>",
+    "dict alias <key> <other>",
+    "dict stats",
+    "source <filename>",
     "help",
 ];
 
+// Number of placements shown by the suggest command
+const SUGGESTIONS: usize = 5;
+
+// Maximum edit distance of keys shown by the keys like command
+const SIMILAR_KEY_DISTANCE: usize = 3;
+
+// Number of words shown by the search command, except in json output
+const SEARCH_RESULTS: usize = 100;
+
+// Maximum number of words in phrases found by the anagram phrase command
+const ANAGRAM_PHRASE_WORDS: usize = 3;
+
+// Number of phrases shown by the solution candidates command
+const SOLUTION_PHRASES: usize = 10;
+
 pub struct KryssKeywordExpander {
     keys: Vec<String>,
     candidates: HashMap<String, Vec<String>>,
 }
 
 impl KryssKeywordExpander {
-    pub fn new(board: &Board) -> Self {
+    // Candidates are offered best first, as ranked by their scores
+    pub fn new(board: &Board, dict: &Dictionary) -> Self {
         let keys = board.words.iter()
             .filter(|w| w.key.is_some())
             .map(|w| w.key.as_ref().unwrap().clone())
@@ -43,26 +93,26 @@ impl KryssKeywordExpander {
 
         let mut candidates: HashMap<String, Vec<String>> = HashMap::new();
 
-        for w in &board.words {
-            if let Some(k) = &w.key {
-                if let Some(c) = candidates.remove(k) {
-                    let mut hs: HashSet<&String> =
-                        HashSet::from_iter(c.iter().collect::<Vec<&String>>());
-                    for cw in &w.candidates {
-                        hs.insert(&cw);
+        for i in 0..board.words.len() {
+            let ranked: Vec<String> = board.ranked_candidates(i, dict)
+                .into_iter()
+                .map(|(c, _)| c)
+                .collect();
+
+            if let Some(k) = &board.words[i].key {
+                // Words may share the same key. Merge their candidates.
+                let c = candidates.entry(k.clone()).or_default();
+                let mut hs: HashSet<String> = HashSet::from_iter(
+                    c.iter().cloned());
+
+                for cw in &ranked {
+                    if hs.insert(cw.clone()) {
+                        c.push(cw.clone());
                     }
-                    candidates.insert(k.clone(), hs.drain().map(
-                        |cw| cw.to_string()).collect());
-                }
-                else {
-                    candidates.insert(k.clone(), w.candidates.clone());
                 }
             }
-        }
 
-        for i in 0..board.words.len() {
-            let w = &board.words[i];
-            candidates.insert(i.to_string(), w.candidates.clone());
+            candidates.insert(i.to_string(), ranked);
         }
 
         Self {
@@ -105,18 +155,209 @@ impl KeywordExpander for KryssKeywordExpander {
     }
 }
 
+// Observer printing board and dictionary events to the terminal. Events go
+// to stderr, to keep them apart from json output.
+pub struct PrintObserver {}
+
+impl PrintObserver {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Observer for PrintObserver {
+    fn notify(&self, event: &BoardEvent) {
+        match event {
+            BoardEvent::Placed { index, key: Some(k), word } => {
+                eprintln!("Placing word [{}] {} = {}", index, k, word);
+            },
+            BoardEvent::Placed { index, key: None, word } => {
+                eprintln!("Placing word [{}] {}", index, word);
+            },
+            BoardEvent::Unplaced { index, key: Some(k), word } => {
+                eprintln!("Unplacing word [{}] {} = {}", index, k, word);
+            },
+            BoardEvent::Unplaced { index, key: None, word } => {
+                eprintln!("Unplacing word [{}] {}", index, word);
+            },
+            BoardEvent::LookupStarted(key) => {
+                eprintln!("Looking up {} from gratiskryssord", key);
+            },
+            BoardEvent::LookupSkipped(key) => {
+                eprintln!("Skip looking up unknown word {}", key);
+            },
+            BoardEvent::LookupFailed { key, error } => {
+                eprintln!("Lookup of {} failed: {}", key, error);
+            },
+            BoardEvent::WordAdded { key, word } => {
+                eprintln!("Adding {} = {} to dictionary", key, word);
+            },
+            BoardEvent::SimilarKeys { key, similar } => {
+                eprintln!("Unknown key {}. Did you mean {}?", key,
+                          similar.join(", "));
+            },
+            BoardEvent::FuzzyKeyUsed { key, used } => {
+                eprintln!("Unknown key {}. Using {}", key, used);
+            },
+            BoardEvent::UnknownKeyAdded(key) => {
+                eprintln!("Adding word to unknown key {}", key);
+            },
+            BoardEvent::AutosaveFailed(e) => {
+                eprintln!("Autosave failed: {}", e);
+            },
+            BoardEvent::StoreFailed(e) => {
+                eprintln!("Unable to read the dictionary: {}", e);
+            },
+            BoardEvent::Listening(address) => {
+                eprintln!("Listening on http://{}", address);
+            },
+            BoardEvent::ResponseFailed(e) => {
+                eprintln!("Unable to send response: {}", e);
+            },
+        }
+    }
+}
+
+// Split a command line into words. Double quotes group words containing
+// spaces, e.g. keys like "spansk elv".
+fn split_line(line: &str) -> Vec<String> {
+    let mut parts = vec!();
+    let mut part = String::new();
+    let mut quoted = false;
+    let mut in_part = false;
+
+    for c in line.chars() {
+        if c == '"' {
+            quoted = !quoted;
+            in_part = true;
+        }
+        else if c.is_whitespace() && !quoted {
+            if in_part {
+                parts.push(part.clone());
+                part.clear();
+                in_part = false;
+            }
+        }
+        else {
+            part.push(c);
+            in_part = true;
+        }
+    }
+
+    if in_part {
+        parts.push(part);
+    }
+
+    return parts;
+}
+
 pub struct KryssApp {
     dict: Dictionary,
     board: Board,
+    pub stop_on_error: bool,
+    pub json: bool,
+    autosave: bool,
+    // Scripts being run, innermost last. A script which sources itself is
+    // refused, and questions aren't asked while a script runs.
+    scripts: Vec<String>,
 }
 
 impl KryssApp {
-    pub fn new(dict: Dictionary, board: Board) -> Self
+    pub fn new(mut dict: Dictionary, mut board: Board) -> Self
     {
+        let observer: Rc<dyn Observer> = Rc::new(PrintObserver::new());
+        dict.set_observer(Some(observer.clone()));
+        board.set_observer(Some(observer));
+
         Self {
             dict: dict,
             board: board,
+            stop_on_error: true,
+            json: false,
+            autosave: false,
+            scripts: vec!(),
+        }
+    }
+
+    // Split a script line into command and arguments by matching the leading
+    // words against the command list. The longest matching command wins, so
+    // that e.g. 'store board' is preferred to 'store'.
+    fn parse_line(line: &str) -> (String, Vec<String>) {
+        let parts = split_line(line);
+        let mut best = 0;
+
+        for c in COMMAND_LIST {
+            let fixed: Vec<&str> = c.split(' ')
+                .take_while(|p| !p.starts_with('<') && !p.starts_with('['))
+                .collect();
+
+            if fixed.len() > best && parts.len() >= fixed.len() &&
+                fixed.iter().zip(parts.iter()).all(|(f, p)| f == p) {
+                    best = fixed.len();
+                }
+        }
+
+        if best == 0 {
+            // Unknown command. Pass the first word on to get an error.
+            return (parts.first().cloned().unwrap_or_default(),
+                    parts.into_iter().skip(1).collect());
+        }
+
+        return (parts[0..best].join(" "), parts[best..].to_vec());
+    }
+
+    // Execute the commands in a script file, one command per line. Empty
+    // lines and lines starting with '#' are skipped. Errors are reported
+    // with file name and line number. Unless stop_on_error is cleared, the
+    // script is aborted at the first failing line.
+    pub fn run_script(&mut self, fname: &str) -> Result<(), String> {
+        let data = read_to_string(fname)
+            .map_err(|e| format!("Unable to read {}: {}", fname, e))?;
+        let path = canonicalize(fname)
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or(fname.to_string());
+
+        if self.scripts.contains(&path) {
+            return Err(format!("Script {} is already running", fname));
+        }
+
+        self.scripts.push(path);
+        let ret = self.run_script_lines(fname, &data);
+        self.scripts.pop();
+
+        return ret;
+    }
+
+    fn run_script_lines(&mut self, fname: &str, data: &str)
+                        -> Result<(), String> {
+        let mut errors = 0;
+
+        for (i, line) in data.lines().enumerate() {
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let (cmd, args) = Self::parse_line(trimmed);
+
+            if let Err(e) = self.execute_line(&cmd, &args) {
+                eprintln!("{}:{}: {}", fname, i + 1, e);
+                errors += 1;
+
+                if self.stop_on_error {
+                    return Err(format!("Script {} stopped at line {}",
+                                       fname, i + 1));
+                }
+            }
+        }
+
+        if errors > 0 {
+            return Err(format!("Script {} had {} failing lines",
+                               fname, errors));
         }
+
+        return Ok(());
     }
 
     fn find_word(&self, key: &str) -> Result<usize, String> {
@@ -125,17 +366,19 @@ impl KryssApp {
         }
 
         let mut hits = vec!();
+        let nkey = normalize_key(key);
+        let nword = normalize_word(key);
 
         for (a, w) in self.board.words.iter().enumerate() {
             if let Some(wkey) = &w.key {
-                if wkey == key {
+                if *wkey == nkey {
                     hits.push(a);
                     continue;
                 }
             }
 
             if w.placed {
-                if w.candidates[0] == key {
+                if w.candidates[0] == nword {
                     hits.push(a);
                     continue;
                 }
@@ -150,8 +393,16 @@ impl KryssApp {
                 return Ok(hits[0]);
             },
             _ => {
+                // The choices are not output of the command, and go to
+                // stderr like the other questions
                 for a in &hits {
-                    println!("{}", self.board.format_word(*a))
+                    eprintln!("{}", self.board.format_word(*a))
+                }
+
+                // There is no one to ask while a script runs
+                if !self.scripts.is_empty() {
+                    return Err(format!("Ambiguous word {}. Use the index.",
+                                       key));
                 }
 
                 let mut buf = String::new();
@@ -171,20 +422,30 @@ impl KryssApp {
         }
     }
 
-    fn solve(&mut self) {
+    fn print_json(&self, value: &Value) {
+        println!("{}", serde_json::to_string_pretty(value).unwrap());
+    }
+
+    fn solve(&mut self, json: bool) {
         self.board.solve_repeated(&mut self.dict);
 
+        if json {
+            self.print_json(&board_json(&self.board));
+            return;
+        }
+
         if self.board.state == State::Solved {
             println!("Solved");
             println!();
-            self.show_board();
+            self.show_board(false);
         }
     }
 
     fn show_words(&self, skip_placed: bool, skip_missing: bool,
-                  skip_ambiguous: bool) {
+                  skip_ambiguous: bool, json: bool) {
         let mut width = 0;
         let mut lines = vec!();
+        let mut values = vec!();
 
         for (a, w) in self.board.words.iter().enumerate() {
             if w.placed && skip_placed {
@@ -199,188 +460,718 @@ impl KryssApp {
                 continue;
             }
 
+            if json {
+                values.push(word_json(&self.board, a));
+                continue;
+            }
+
             let line = format!("{}", self.board.format_word(a));
             width = max(width, line.len());
             lines.push(line);
         }
 
+        if json {
+            self.print_json(&Value::Array(values));
+            return;
+        }
+
         self.print_columns(&lines, width);
     }
 
-    fn show_solution(&self) {
-        println!("{}", self.board.words.iter().enumerate()
-                 .filter(|(_, w)|
-                         w.key.is_none()
-                 )
-                 .map(
-                     |(i, _)|
-                     self.board.get_hints(i)
-                 )
-                 .collect::<Vec<String>>()
-                 .join(" "));
+    fn show_solution(&self, json: bool) {
+        let indexes = self.board.solution_words();
+        let words = indexes.iter()
+            .map(|i| self.board.get_hints(*i))
+            .collect::<Vec<String>>();
+
+        if json {
+            self.print_json(&json!({
+                "words": words,
+                "cells": indexes.iter()
+                    .map(|i| &self.board.words[*i].cells)
+                    .collect::<Vec<_>>(),
+                "solution": words.join(" "),
+            }));
+            return;
+        }
+
+        println!("{}", words.join(" "));
+
+        // Numbered cells of the words made of individual cells
+        for (i, hint) in indexes.iter().zip(words.iter()) {
+            let cells: Vec<String> = self.board.words[*i].cells.iter()
+                .zip(hint.chars())
+                .enumerate()
+                .map(|(n, ((x, y), c))| format!("{}: {} ({}, {})",
+                                                n + 1, c, x, y))
+                .collect();
+
+            if !cells.is_empty() {
+                println!("  [{}] {}", i, cells.join("  "));
+            }
+        }
+    }
+
+    fn solution_candidates(&self, json: bool) {
+        let phrases = self.board.solution_phrases(&self.dict,
+                                                  SOLUTION_PHRASES);
+
+        if json {
+            self.print_json(&Value::Array(phrases.iter().map(
+                |(p, score)| json!({
+                    "words": p,
+                    "display": p.iter()
+                        .map(|w| self.dict.word_display_form(w))
+                        .collect::<Vec<String>>(),
+                    "score": score,
+                })).collect()));
+            return;
+        }
+
+        if phrases.is_empty() {
+            println!("No candidates");
+            return;
+        }
+
+        for (p, score) in &phrases {
+            let display: Vec<String> = p.iter()
+                .map(|w| self.dict.word_display_form(w))
+                .collect();
+            println!("  {}  ({:.1})", display.join(" "), score);
+        }
+    }
+
+    // Place one word for each word of the solution sentence
+    fn place_solution(&mut self, words: &Vec<String>) -> Result<(), String> {
+        let indexes = self.board.solution_words();
+
+        if words.len() != indexes.len() {
+            return Err(format!("Expected {} words", indexes.len()));
+        }
+
+        let grids: Vec<String> = words.iter()
+            .map(|w| Dictionary::normalize(w))
+            .collect();
+
+        for (a, grid) in indexes.iter().zip(grids.iter()) {
+            if self.board.words[*a].length != grid.chars().count() {
+                return Err(format!("Invalid length of {}", grid));
+            }
+
+            if !grid.contains('.') && !self.dict.in_alphabet(grid) {
+                return Err(format!("Invalid letters in {}", grid));
+            }
+        }
+
+        // Words with unknown letters are left as they are
+        for (a, grid) in indexes.into_iter().zip(grids.into_iter()) {
+            if !grid.contains('.') {
+                self.board.place(a, Some(grid), &mut self.dict);
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn suggest(&self, json: bool) {
+        let suggestions: Vec<Suggestion> = self.board.suggest(&self.dict)
+            .into_iter()
+            .take(SUGGESTIONS)
+            .collect();
+
+        if json {
+            self.print_json(&Value::Array(suggestions.iter().map(
+                |s| json!({
+                    "index": s.index,
+                    "word": s.word,
+                    "eliminated": s.eliminated,
+                    "solved": s.solved,
+                    "emptied": s.emptied,
+                })).collect()));
+            return;
+        }
+
+        if suggestions.is_empty() {
+            println!("No ambiguous words");
+            return;
+        }
+
+        for s in &suggestions {
+            if let Some(k) = &self.board.words[s.index].key {
+                println!("[{}] {} = {}", s.index, k, s.word);
+            }
+            else {
+                println!("[{}] {}", s.index, s.word);
+            }
+
+            println!("  Removes {} candidates from crossing words",
+                     s.eliminated);
+
+            for b in &s.solved {
+                println!("  Leaves one candidate for {}",
+                         self.board.format_word(*b));
+            }
+
+            for b in &s.emptied {
+                println!("  Leaves no candidates for {}",
+                         self.board.format_word(*b));
+            }
+        }
     }
 
-    fn show_board(&self) {
+    fn show_board(&self, json: bool) {
+        if json {
+            self.print_json(&board_json(&self.board));
+            return;
+        }
+
         println!("{}", self.board.to_string());
         println!();
     }
 
-    fn show_crossing(&self, key: usize) {
+    fn show_crossing(&self, key: usize, json: bool) {
+        if json {
+            self.print_json(&json!({
+                "word": word_json(&self.board, key),
+                "crossings": crossings_json(&self.board, key),
+            }));
+            return;
+        }
+
         if !self.board.crossings.contains_key(&key) {
             println!("No crossing words for key");
             return;
         }
 
         println!("{}", self.board.format_word(key));
-        self.board.show_crossing(key);
+        print!("{}", self.board.crossing_to_string(key));
     }
 
-    fn show_candidates(&self, key: usize) {
-        for c in &self.board.words[key].candidates {
-            println!("  {}", c);
+    fn show_candidates(&self, key: usize, json: bool) {
+        if json {
+            self.print_json(&json!({
+                "index": key,
+                "candidates": ranked_candidates_json(&self.board, key,
+                                                     &self.dict),
+            }));
+            return;
+        }
+
+        // Show the original form of phrases next to the grid form, where
+        // the candidates came from, and how derived candidates were found
+        let wkey = self.board.words[key].key.clone().unwrap_or_default();
+        let ranked: Vec<(String, f64, String)> = self.board
+            .ranked_candidates(key, &self.dict).iter()
+            .map(|(c, s)| {
+                let source = match self.dict.provenance(&wkey, c) {
+                    Some(p) => format!("  ({})", p),
+                    None => "".to_string(),
+                };
+                let d = self.dict.display_form(&wkey, c);
+                let label = if &d == c {
+                    c.clone()
+                }
+                else {
+                    format!("{} ({})", c, d)
+                };
+                let derivation = match self.dict.derivation(&wkey, c) {
+                    Some(d) => format!("  [{}]", d),
+                    None => "".to_string(),
+                };
+
+                (label, s.total(), source + &derivation)
+            })
+            .collect();
+        let width = ranked.iter()
+            .map(|(c, _, _)| c.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        for (c, t, d) in &ranked {
+            println!("  {:width$}  {:.1}{}", c, t, d, width = width);
         }
     }
 
-    fn info_word(&self, key: usize) {
+    fn info_word(&self, key: usize, json: bool) {
+        if json {
+            let mut value = word_json(&self.board, key);
+            value["crossings"] = crossings_json(&self.board, key);
+            value["provenance"] = provenance_json(&self.board, key,
+                                                  &self.dict);
+            self.print_json(&value);
+            return;
+        }
+
         println!("{}", self.board.format_word(key));
-        self.board.info_word(&key);
+        println!("{}", self.board.info_to_string(key, &self.dict));
         println!();
-        self.board.show_crossing(key);
+        print!("{}", self.board.crossing_to_string(key));
     }
 
     fn set_colors(&mut self, on: bool) {
         self.board.colors = on;
     }
 
-    fn place(&mut self, key: usize, word: &str) {
-        if self.board.words[key].length != word.chars().count() {
-            println!("Invalid length.");
-            return;
+    // Autosave writes recovery snapshots after each command, and
+    // periodically while a command places words or looks up words from
+    // the web.
+    pub fn set_autosave(&mut self, on: bool) {
+        self.autosave = on;
+        self.board.autosave = on;
+        self.dict.autosave = on;
+    }
+
+    fn place(&mut self, key: usize, word: &str) -> Result<(), String> {
+        // Phrases are placed without spaces and hyphens
+        let grid = Dictionary::normalize(word);
+
+        if self.board.words[key].length != grid.chars().count() {
+            return Err("Invalid length.".to_string());
+        }
+
+        if !self.dict.in_alphabet(&grid) {
+            return Err("Invalid letters.".to_string());
         }
 
-        self.board.place(key, Some(word.to_string()), &mut self.dict);
+        self.board.place(key, Some(grid.clone()), &mut self.dict);
 
         // Add word to dictionary if missing
         if let Some(k) = &self.board.words[key].key {
-            self.dict.add_word(&k, word);
+            self.dict.add_word(&k, word, Source::Placed);
+            self.dict.record_choice(&k, &grid);
         }
+
+        return Ok(());
     }
 
-    fn lookup(&mut self, key: &str, length: usize, opt_hint: Option<&str>) {
-        for w in &self.dict.lookup(key, length, opt_hint) {
-            print!("{} ", w);
+    fn lookup(&mut self, key: &str, length: usize, opt_hint: Option<&str>,
+              json: bool) {
+        let words = self.dict.lookup(key, length, opt_hint);
+
+        if json {
+            self.print_json(&json!({
+                "key": key,
+                "length": length,
+                "candidates": words,
+                "display": words.iter()
+                    .map(|w| self.dict.display_form(key, w))
+                    .collect::<Vec<String>>(),
+            }));
+            return;
+        }
+
+        for w in &words {
+            print!("{} ", self.dict.display_form(key, w));
         }
         println!();
     }
 
-    fn store_board(&mut self, opt_fname: Option<&str>) {
-        self.board.write_to_file(opt_fname);
+    fn search(&self, pattern: &str, json: bool) -> Result<(), String> {
+        let pattern = Pattern::parse(pattern)?;
+        let found = self.dict.search(&pattern);
+
+        if json {
+            self.print_json(&Value::Array(found.iter().map(
+                |(w, keys)| json!({
+                    "word": w,
+                    "display": self.dict.word_display_form(w),
+                    "keys": keys,
+                    "frequency": self.dict.frequency(w),
+                })).collect()));
+            return Ok(());
+        }
+
+        for (w, keys) in found.iter().take(SEARCH_RESULTS) {
+            println!("  {}  {}", self.dict.word_display_form(w),
+                     keys.join(", "));
+        }
+
+        if found.len() > SEARCH_RESULTS {
+            println!("  ... and {} more", found.len() - SEARCH_RESULTS);
+        }
+
+        return Ok(());
+    }
+
+    fn anagram_args(args: &Vec<String>)
+                    -> Result<(&str, Option<&str>), String> {
+        if args.is_empty() || args.len() > 2 {
+            return Err("Expected letters and an optional hint".to_string());
+        }
+
+        Ok((&args[0], <dyn CmdApp>::opt_part(args, 1)))
+    }
+
+    fn anagram(&self, letters: &str, opt_hint: Option<&str>, json: bool)
+               -> Result<(), String> {
+        let opt_pattern = opt_hint.map(Pattern::parse).transpose()?;
+        let words = self.dict.anagrams(letters, opt_pattern.as_ref());
+        let display: Vec<String> = words.iter()
+            .map(|w| self.dict.word_display_form(w))
+            .collect();
+
+        if json {
+            self.print_json(&json!({
+                "letters": letters,
+                "words": words,
+                "display": display,
+            }));
+            return Ok(());
+        }
+
+        println!("{}", display.join(" "));
+
+        return Ok(());
+    }
+
+    fn anagram_phrase(&self, letters: &str, opt_hint: Option<&str>,
+                      json: bool) -> Result<(), String> {
+        let opt_pattern = opt_hint.map(Pattern::parse).transpose()?;
+        let phrases = self.dict.anagram_phrases(letters, opt_pattern.as_ref(),
+                                                ANAGRAM_PHRASE_WORDS);
+
+        if json {
+            self.print_json(&json!({
+                "letters": letters,
+                "phrases": phrases,
+            }));
+            return Ok(());
+        }
+
+        for p in phrases.iter().take(SEARCH_RESULTS) {
+            let display: Vec<String> = p.iter()
+                .map(|w| self.dict.word_display_form(w))
+                .collect();
+            println!("  {}", display.join(" "));
+        }
+
+        if phrases.len() > SEARCH_RESULTS {
+            println!("  ... and {} more", phrases.len() - SEARCH_RESULTS);
+        }
+
+        return Ok(());
+    }
+
+    fn keys_like(&self, text: &str, json: bool) {
+        let similar = self.dict.similar_keys(text, SIMILAR_KEY_DISTANCE);
+
+        if json {
+            self.print_json(&Value::Array(similar.iter().map(
+                |(k, d)| json!({ "key": k, "distance": d })).collect()));
+            return;
+        }
+
+        for (k, d) in &similar {
+            println!("  {} ({})", k, d);
+        }
+    }
+
+    fn store_board(&mut self, opt_fname: Option<&str>)
+                   -> Result<(), String> {
+        self.board.write_to_file(opt_fname)
+    }
+
+    fn store_session(&mut self, opt_fname: Option<&str>)
+                     -> Result<(), String> {
+        self.board.write_session_file(opt_fname)
     }
 
-    fn store_dictionary(&mut self, opt_fname: Option<&str>) {
-        self.dict.write_to_file(opt_fname);
+    fn store_dictionary(&mut self, opt_fname: Option<&str>)
+                        -> Result<(), String> {
+        self.dict.write_to_file(opt_fname)
     }
 
     fn add_word(&mut self, key: &str, word: &str) {
-        self.dict.add_word(key, word);
+        self.dict.add_word(key, word, Source::Manual);
     }
 
-    fn help(&self) {
-        println!("{}", COMMAND_LIST.into_iter()
-                 .map(|c| c.replace("<bool>", "on/off"))
-                 .collect::<Vec<String>>()
-                 .join("\n")
-        );
+    fn learn(&mut self, json: bool) {
+        let count = self.board.learn(&mut self.dict);
+
+        if json {
+            self.print_json(&json!({ "learned": count }));
+            return;
+        }
+
+        println!("Learned {} words", count);
     }
-}
 
-impl CmdApp for KryssApp {
-    fn command_list<'a>(&self) -> &'a [&'a str] {
-        return COMMAND_LIST;
+    fn import_frequencies(&mut self, fname: &str, json: bool)
+                          -> Result<(), String> {
+        let count = self.dict.import_frequencies(fname)?;
+
+        if json {
+            self.print_json(&json!({ "imported": count }));
+            return Ok(());
+        }
+
+        println!("Imported {} word frequencies", count);
+
+        return Ok(());
     }
 
-    fn execute_line(&mut self, cmd: &str, args: &Vec<String>)
-                    -> Result<(), String> {
+    fn dict_show(&self, key: &str, json: bool) -> Result<(), String> {
+        let entries = self.dict.entries(key)
+            .ok_or(format!("Unknown key {}", key))?;
+        let alias = self.dict.alias_of(key);
+
+        if json {
+            self.print_json(&json!({
+                "key": key,
+                "alias": alias,
+                "words": entries.iter()
+                    .map(|(l, w)| json!({
+                        "length": l,
+                        "words": w,
+                        "provenance": w.iter()
+                            .map(|w| self.dict.provenance(key, w))
+                            .collect::<Vec<_>>(),
+                    }))
+                    .collect::<Vec<Value>>(),
+            }));
+            return Ok(());
+        }
+
+        if let Some(k) = alias {
+            println!("{} is an alias of {}", key, k);
+        }
+
+        for (l, words) in &entries {
+            for w in words {
+                match self.dict.provenance(key, w) {
+                    Some(p) => println!("  {}: {}  ({})", l,
+                                        self.dict.display_form(key, w), p),
+                    None => println!("  {}: {}", l,
+                                     self.dict.display_form(key, w)),
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn dict_stats(&self, json: bool) {
+        let stats = self.dict.stats();
+
+        if json {
+            self.print_json(&json!({
+                "keys": stats.keys,
+                "empty_keys": stats.empty_keys,
+                "words": stats.words,
+                "distinct_words": stats.distinct_words,
+                "aliases": stats.aliases,
+                "phrases": stats.phrases,
+                "frequencies": stats.frequencies,
+            }));
+            return;
+        }
+
+        println!("Keys:           {}", stats.keys);
+        println!("Empty keys:     {}", stats.empty_keys);
+        println!("Words:          {}", stats.words);
+        println!("Distinct words: {}", stats.distinct_words);
+        println!("Aliases:        {}", stats.aliases);
+        println!("Phrases:        {}", stats.phrases);
+        println!("Frequencies:    {}", stats.frequencies);
+    }
+
+    fn execute_command(&mut self, cmd: &str, args: &Vec<String>)
+                       -> Result<(), String> {
+        // A '--json' argument turns on json output for this command only
+        let json = self.json || args.iter().any(|a| a == "--json");
+        let args = &args.iter()
+            .filter(|a| *a != "--json")
+            .cloned()
+            .collect::<Vec<String>>();
+
         match cmd {
             "solve" => {
-                self.solve();
+                self.solve(json);
             },
             "words" => {
-                self.show_words(false, false, false);
+                self.show_words(false, false, false, json);
             },
             "placed" => {
-                self.show_words(false, true, true);
+                self.show_words(false, true, true, json);
             },
             "unplaced" => {
-                self.show_words(true, false, false);
+                self.show_words(true, false, false, json);
             },
             "missing" => {
-                self.show_words(true, false, true);
+                self.show_words(true, false, true, json);
             },
             "ambiguous" => {
-                self.show_words(true, true, false);
+                self.show_words(true, true, false, json);
             },
             "crossing" => {
                 <dyn CmdApp>::expects_num_arguments(args, 1)?;
                 let key_part = &args[0];
                 let key = self.find_word(&key_part)?;
 
-                self.show_crossing(key);
+                self.show_crossing(key, json);
             },
             "candidates" => {
                 <dyn CmdApp>::expects_num_arguments(args, 1)?;
                 let key_part = &args[0];
                 let key = self.find_word(&key_part)?;
 
-                self.show_candidates(key);
+                self.show_candidates(key, json);
             },
             "solution" => {
-                self.show_solution();
+                self.show_solution(json);
+            },
+            "solution candidates" => {
+                self.dict.read_all()?;
+                self.solution_candidates(json);
+            },
+            "solution place" => {
+                self.place_solution(args)?;
+            },
+            "suggest" => {
+                self.suggest(json);
             },
             "board" => {
-                self.show_board();
+                self.show_board(json);
             },
             "info" => {
                 <dyn CmdApp>::expects_num_arguments(args, 1)?;
                 let key_part = &args[0];
                 let key = self.find_word(&key_part)?;
 
-                self.info_word(key);
+                self.info_word(key, json);
             },
             "set colors" => {
                 <dyn CmdApp>::expects_num_arguments(args, 1)?;
                 self.set_colors(
                     <dyn CmdApp>::parse_bool(&args[0])?);
             },
+            "set stoponerror" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.stop_on_error = <dyn CmdApp>::parse_bool(&args[0])?;
+            },
+            "set json" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.json = <dyn CmdApp>::parse_bool(&args[0])?;
+            },
+            "set transliterate" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.dict.transliterate = <dyn CmdApp>::parse_bool(&args[0])?;
+            },
+            "set inflect" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.dict.inflect = <dyn CmdApp>::parse_bool(&args[0])?;
+            },
+            "set learn" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.dict.learn = <dyn CmdApp>::parse_bool(&args[0])?;
+            },
+            "set synonyms" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.dict.synonyms = <dyn CmdApp>::parse_bool(&args[0])?;
+            },
+            "set fuzzy" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.dict.fuzzy_keys = <dyn CmdApp>::parse_bool(&args[0])?;
+            },
+            "set alphabet" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.dict.set_alphabet(&args[0]);
+            },
+            "set backups" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                let backups = <dyn CmdApp>::parse_int(&args[0])?;
+                self.board.backups = backups;
+                self.dict.backups = backups;
+            },
+            "set autosave" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.set_autosave(<dyn CmdApp>::parse_bool(&args[0])?);
+            },
             "place" => {
                 <dyn CmdApp>::expects_num_arguments(args, 2)?;
                 let key_part = &args[0];
                 let key = self.find_word(&key_part)?;
                 let word = &args[1];
 
-                self.place(key, &word);
+                self.place(key, &word)?;
             },
             "lookup" => {
                 <dyn CmdApp>::expects_num_arguments(args, 2)?;
                 let word = &args[0];
                 let param = &args[1];
                 if let Ok(length) = <dyn CmdApp>::parse_int(param) {
-                    self.lookup(&word, length, None);
+                    self.lookup(&word, length, None, json);
                 }
                 else {
-                    self.lookup(&word, param.len(), Some(param));
+                    // The hint is matched in grid form
+                    let length = normalize_word(param).chars().count();
+                    self.lookup(&word, length, Some(param), json);
                 }
             },
+            "search" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.dict.read_all()?;
+                self.search(&args[0], json)?;
+            },
+            "anagram" => {
+                let (letters, opt_hint) = Self::anagram_args(args)?;
+                self.dict.read_all()?;
+                self.anagram(letters, opt_hint, json)?;
+            },
+            "anagram phrase" => {
+                let (letters, opt_hint) = Self::anagram_args(args)?;
+                self.dict.read_all()?;
+                self.anagram_phrase(letters, opt_hint, json)?;
+            },
+            "keys like" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.keys_like(&args[0], json);
+            },
             "store board" => {
-                self.store_board(<dyn CmdApp>::opt_part(args, 0));
+                self.store_board(<dyn CmdApp>::opt_part(args, 0))?;
             },
             "store dictionary" => {
-                self.store_dictionary(<dyn CmdApp>::opt_part(args, 0));
+                self.store_dictionary(<dyn CmdApp>::opt_part(args, 0))?;
+            },
+            "store session" => {
+                self.store_session(<dyn CmdApp>::opt_part(args, 0))?;
             },
-            "add " => {
+            "add" => {
                 <dyn CmdApp>::expects_num_arguments(args, 2)?;
                 self.add_word(&args[0], &args[1]);
             },
+            "learn" => {
+                self.learn(json);
+            },
+            "import frequencies" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.import_frequencies(&args[0], json)?;
+            },
+            "dict show" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.dict.load_key(&args[0]);
+                self.dict_show(&args[0], json)?;
+            },
+            "dict remove" => {
+                <dyn CmdApp>::expects_num_arguments(args, 2)?;
+                self.dict.remove_word(&args[0], &args[1])?;
+            },
+            "dict forget" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.dict.forget_key(&args[0])?;
+            },
+            "dict alias" => {
+                <dyn CmdApp>::expects_num_arguments(args, 2)?;
+                self.dict.alias_key(&args[0], &args[1])?;
+            },
+            "dict stats" => {
+                self.dict.read_all()?;
+                self.dict_stats(json);
+            },
+            "source" => {
+                <dyn CmdApp>::expects_num_arguments(args, 1)?;
+                self.run_script(&args[0])?;
+            },
             "help" => {
                 self.help();
             }
@@ -393,24 +1184,159 @@ impl CmdApp for KryssApp {
         Ok(())
     }
 
+    fn help(&self) {
+        println!("{}", COMMAND_LIST.into_iter()
+                 .map(|c| c.replace("<bool>", "on/off"))
+                 .collect::<Vec<String>>()
+                 .join("\n")
+        );
+    }
+
+    // Remove the recovery snapshots, e.g. at the end of a script. The
+    // snapshot of a file restored from it is kept until the file is
+    // stored.
+    pub fn remove_recovery(&self, board_restored: bool,
+                           dict_restored: bool) {
+        if !(board_restored && self.board.changed) {
+            remove_recovery(&self.board.filename);
+        }
+
+        if !(dict_restored && self.dict.changed) {
+            remove_recovery(&self.dict.filename);
+        }
+    }
+
+    // Write recovery snapshots of the board and dictionary if they have
+    // changed since their last snapshot. Unchanged files are not written
+    // again, since a large dictionary is slow to write.
+    pub fn write_recovery(&mut self) -> Result<(), String> {
+        if self.board.changed && self.board.dirty_since_snapshot {
+            self.board.write_recovery()?;
+        }
+
+        if self.dict.changed && self.dict.dirty_since_snapshot {
+            self.dict.write_recovery()?;
+        }
+
+        return Ok(());
+    }
+}
+
+impl CmdApp for KryssApp {
+    fn command_list<'a>(&self) -> &'a [&'a str] {
+        return COMMAND_LIST;
+    }
+
+    fn execute_line(&mut self, cmd: &str, args: &Vec<String>)
+                    -> Result<(), String> {
+        let ret = self.execute_command(cmd, args);
+
+        if self.autosave {
+            if let Err(e) = self.write_recovery() {
+                let e = format!("Autosave failed: {}", e);
+
+                // The error of the command itself is returned
+                if ret.is_ok() {
+                    return Err(e);
+                }
+
+                eprintln!("{}", e);
+            }
+        }
+
+        return ret;
+    }
+
     fn startup(&mut self) {
-        self.solve();
-        self.show_board();
+        // In json mode the solved board is printed once, so that the
+        // startup output is a single json document
+        if self.json {
+            self.board.solve_repeated(&mut self.dict);
+        }
+        else {
+            self.solve(false);
+        }
+
+        self.show_board(self.json);
+
+        if self.autosave {
+            if let Err(e) = self.write_recovery() {
+                eprintln!("{}", e);
+            }
+        }
     }
 
     fn exit(&mut self) {
+        // The recovery snapshot of a file which could not be stored is
+        // kept. Questions go to stderr, so that stdout only has the output
+        // of commands.
+        let mut board_stored = true;
+        let mut dict_stored = true;
+
         if self.board.changed {
-            println!("Save changes to {}? (Y/n)", self.board.filename);
+            eprintln!("Save changes to {}? (Y/n)", self.board.filename);
             if self.confirm_yes_no() {
-                self.store_board(None);
+                if let Err(e) = self.store_board(None) {
+                    eprintln!("{}", e);
+                    board_stored = false;
+                }
             }
         }
 
         if self.dict.changed {
-            println!("Save dictionary to {}? (Y/n)", self.dict.filename);
+            eprintln!("Save dictionary to {}? (Y/n)", self.dict.filename);
             if self.confirm_yes_no() {
-                self.store_dictionary(None);
+                if let Err(e) = self.store_dictionary(None) {
+                    eprintln!("{}", e);
+                    dict_stored = false;
+                }
             }
         }
+
+        // The recovery snapshots are no longer needed
+        if board_stored {
+            remove_recovery(&self.board.filename);
+        }
+
+        if dict_stored {
+            remove_recovery(&self.dict.filename);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(split_line("  place 12   ebro "),
+                   vec!["place", "12", "ebro"]);
+        assert!(split_line("   ").is_empty());
+    }
+
+    #[test]
+    fn quotes_group_words() {
+        assert_eq!(split_line("place \"spansk elv\" ebro"),
+                   vec!["place", "spansk elv", "ebro"]);
+        assert_eq!(split_line("add \"\" x"), vec!["add", "", "x"]);
+    }
+
+    #[test]
+    fn longest_command_wins() {
+        let (cmd, args) = KryssApp::parse_line("store board out.kryss");
+        assert_eq!(cmd, "store board");
+        assert_eq!(args, vec!["out.kryss"]);
+
+        let (cmd, args) = KryssApp::parse_line("dict show \"spansk elv\"");
+        assert_eq!(cmd, "dict show");
+        assert_eq!(args, vec!["spansk elv"]);
+    }
+
+    #[test]
+    fn unknown_command_is_passed_on() {
+        let (cmd, args) = KryssApp::parse_line("frobnicate a b");
+        assert_eq!(cmd, "frobnicate");
+        assert_eq!(args, vec!["a", "b"]);
     }
 }
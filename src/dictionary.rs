@@ -1,114 +1,2072 @@
 use url::Url;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use std::fmt::{Formatter, Display};
+use std::str::FromStr;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use crate::recovery::{recovery_filename, recover_json_section};
+use crate::recovery::{remove_recovery, autosave_due};
+use crate::event::{BoardEvent, SharedObserver, notify};
+use crate::text::{normalize_key, normalize_word, transliterations};
+use crate::text::{in_alphabet, DEFAULT_ALPHABET};
+use crate::text::{fold_key, edit_distance};
+use crate::inflect::inflections;
+use crate::pattern::Pattern;
+use crate::atomic::{write_file, default_backups, rotate_backups};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SqliteStore;
 use sxd_html::parse_html;
 use sxd_xpath::{Value, evaluate_xpath};
 
+// How a candidate which is not listed for a key was found
+#[derive(Clone, PartialEq)]
+pub enum Derivation {
+    // Answer to another key, which shares answers with the key
+    Synonym(String),
+    // Inflected form of an answer to the key
+    Inflection(String),
+}
+
+impl Display for Derivation {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Derivation::Synonym(k) => write!(f, "synonym via {}", k),
+            Derivation::Inflection(w) => write!(f, "inflection of {}", w),
+        }
+    }
+}
+
+// Where a word for a key came from
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    // Search result from gratiskryssord
+    Web,
+    // Added with the add command
+    Manual,
+    // Added when placed on the board
+    Placed,
+    // Learned from a solved board
+    Learned,
+}
+
+impl Source {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Web => "web",
+            Source::Manual => "manual",
+            Source::Placed => "placed",
+            Source::Learned => "learned",
+        }
+    }
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "web" => Ok(Source::Web),
+            "manual" => Ok(Source::Manual),
+            "placed" => Ok(Source::Placed),
+            "learned" => Ok(Source::Learned),
+            _ => Err(format!("Unknown source {}", s)),
+        }
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Provenance {
+    pub source: Source,
+    // Seconds since the unix epoch
+    pub added: u64,
+}
+
+impl Provenance {
+    pub fn now(source: Source) -> Self {
+        let added = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        Self { source: source, added: added }
+    }
+}
+
+// Date of a unix time as yyyy-mm-dd (days to civil date conversion from
+// http://howardhinnant.github.io/date_algorithms.html)
+fn format_date(secs: u64) -> String {
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+impl Display for Provenance {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.source, format_date(self.added))
+    }
+}
+
+// A change of the entries of a dictionary. The changes of a dictionary in
+// a database are kept until it is stored, so that only the changed rows
+// are written.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Change {
+    AddKey(String),
+    AddWord { key: String, word: String },
+    RemoveWord { key: String, word: String },
+    RemoveKey(String),
+    // Placements of a word, added to the stored count
+    Choice { key: String, word: String, count: u32 },
+    Provenance { key: String, word: String, provenance: Option<Provenance> },
+    Alias { key: String, target: Option<String> },
+    Display { key: String, word: String, form: String },
+    Frequency { word: String, count: u64 },
+}
+
+// Entries which differ between two dictionaries being merged, and which
+// can't both be kept. The entry of the dictionary merged into is kept.
+// Words are not in conflict, since the words of both are kept.
+pub enum MergeConflict {
+    // Different original forms of a word for a key
+    Display {
+        key: String,
+        word: String,
+        ours: String,
+        theirs: String,
+    },
+    // A key which is an alias in one of the dictionaries, but not an alias
+    // of the same key in the other
+    Alias {
+        key: String,
+        ours: Option<String>,
+        theirs: Option<String>,
+    },
+}
+
+impl Display for MergeConflict {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            MergeConflict::Display { key, word, ours, theirs } => {
+                write!(f, "{}: {}: '{}' <> '{}'", key, word, ours, theirs)
+            },
+            MergeConflict::Alias { key, ours, theirs } => {
+                let ours = ours.as_deref().unwrap_or("no alias");
+                let theirs = theirs.as_deref().unwrap_or("no alias");
+                write!(f, "{}: alias {} <> {}", key, ours, theirs)
+            },
+        }
+    }
+}
+
+// Words of a key which are only found in one of two dictionaries
+pub struct KeyDiff {
+    pub key: String,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+pub struct DictionaryStats {
+    pub keys: usize,
+    // Keys without any words, e.g. keys with no result from the web lookup
+    pub empty_keys: usize,
+    pub words: usize,
+    pub distinct_words: usize,
+    pub aliases: usize,
+    pub phrases: usize,
+    pub frequencies: usize,
+}
+
+// Shortest word used in anagram phrases
+const MIN_PHRASE_WORD: usize = 2;
+
+// Maximum number of anagram phrases returned, since short words combine in
+// very many ways
+const MAX_ANAGRAM_PHRASES: usize = 1000;
+
+// Shortest folded key which is matched against close known keys. Short
+// keys are close to many unrelated keys, e.g. 'ål' and 'al'.
+const MIN_FUZZY_KEY: usize = 4;
+
+// Anagram index key of a word
+fn sorted_letters(word: &str) -> String {
+    let mut letters: Vec<char> = word.chars().collect();
+    letters.sort();
+
+    letters.into_iter().collect()
+}
+
+// Check if all letters of a are in b, where both are sorted
+fn is_sub_multiset(a: &[char], b: &[char]) -> bool {
+    let mut j = 0;
+
+    for c in a {
+        while j < b.len() && b[j] < *c {
+            j += 1;
+        }
+
+        if j == b.len() || b[j] != *c {
+            return false;
+        }
+
+        j += 1;
+    }
+
+    return true;
+}
+
+// Letters of b which are not in a, where both are sorted and a is a sub
+// multiset of b
+fn remove_letters(a: &[char], b: &[char]) -> Vec<char> {
+    let mut ret = b.to_vec();
+
+    for c in a {
+        let i = ret.iter().position(|r| r == c).unwrap();
+        ret.remove(i);
+    }
+
+    return ret;
+}
+
+// Combinations of parts which together use exactly the remaining letters.
+// Parts are combined in index order, so that each combination is only found
+// once.
+fn find_combinations(parts: &[(Vec<char>, Vec<&String>)], start: usize,
+                     remaining: &[char], chosen: &mut Vec<usize>,
+                     max_words: usize, out: &mut Vec<Vec<usize>>) {
+    if remaining.is_empty() {
+        out.push(chosen.clone());
+        return;
+    }
+
+    if chosen.len() >= max_words || out.len() >= MAX_ANAGRAM_PHRASES {
+        return;
+    }
+
+    for i in start..parts.len() {
+        if is_sub_multiset(&parts[i].0, remaining) {
+            chosen.push(i);
+            find_combinations(parts, i, &remove_letters(&parts[i].0,
+                                                        remaining),
+                              chosen, max_words, out);
+            chosen.pop();
+        }
+    }
+}
+
+// Phrases for a combination of parts, using each word of the parts
+fn expand_phrases(parts: &[(Vec<char>, Vec<&String>)], combination: &[usize])
+                  -> Vec<Vec<String>> {
+    let mut ret: Vec<Vec<String>> = vec!(vec!());
+
+    for i in combination {
+        ret = ret.into_iter()
+            .flat_map(|phrase| parts[*i].1.iter().map(move |w| {
+                let mut p = phrase.clone();
+                p.push(w.to_string());
+                p
+            }))
+            .collect();
+    }
+
+    return ret;
+}
+
+// All orderings of the words of a phrase
+fn permutations(words: &[String]) -> Vec<Vec<String>> {
+    if words.len() <= 1 {
+        return vec!(words.to_vec());
+    }
+
+    let mut ret = vec!();
+
+    for i in 0..words.len() {
+        let mut rest = words.to_vec();
+        let first = rest.remove(i);
+
+        for mut p in permutations(&rest) {
+            p.insert(0, first.clone());
+            ret.push(p);
+        }
+    }
+
+    return ret;
+}
+
+// Check a word against a hint, where '.' matches any character
+fn matches_hint(word: &str, hint: &str) -> bool {
+    word.chars().zip(hint.chars()).all(|(a, b)| a == b || b == '.')
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Dictionary {
+    // Version of the file format. Files without a version are version 0.
+    #[serde(default)]
+    version: u32,
     words: HashMap<String, HashMap<usize, Vec<String>>>,
+    // Word frequencies from an imported corpus
+    #[serde(default)]
+    frequencies: HashMap<String, u64>,
+    // Number of times each word has been placed for a key
+    #[serde(default)]
+    choices: HashMap<String, HashMap<String, u32>>,
+    // Original form of multi-word answers for each key, e.g. 'gi seg' for
+    // 'giseg'. Words are stored in the form they have in the grid.
+    #[serde(default)]
+    display: HashMap<String, HashMap<String, String>>,
+    // Keys which use the entries of another key
+    #[serde(default)]
+    aliases: HashMap<String, String>,
+    // Source of the words of each key. Words stored before sources were
+    // recorded have none.
+    #[serde(default)]
+    provenance: HashMap<String, HashMap<String, Provenance>>,
     #[serde(skip_serializing, skip_deserializing)]
     pub changed: bool,
+    // Changed since the last recovery snapshot
+    #[serde(skip_serializing, skip_deserializing)]
+    pub dirty_since_snapshot: bool,
     #[serde(skip_serializing, skip_deserializing)]
     pub filename: String,
+    // Write recovery snapshots periodically while the dictionary changes
+    #[serde(skip_serializing, skip_deserializing)]
+    pub autosave: bool,
+    #[serde(skip_serializing, skip_deserializing)]
+    last_autosave: Option<Instant>,
+    #[serde(skip_serializing, skip_deserializing)]
+    observer: SharedObserver,
+    // Letters allowed in the grid. Words with other letters are ignored.
+    #[serde(skip_serializing, skip_deserializing)]
+    alphabet: Option<String>,
+    // Also match words with æ, ø and å written as ae, oe and aa (or the
+    // other way around), as in English sources
+    #[serde(skip_serializing, skip_deserializing)]
+    pub transliterate: bool,
+    // Use a close known key instead of looking up an unknown key
+    #[serde(skip_serializing, skip_deserializing)]
+    pub fuzzy_keys: bool,
+    // Unknown keys resolved to known keys by fuzzy matching
+    #[serde(skip_serializing, skip_deserializing)]
+    resolved: HashMap<String, String>,
+    // Reverse index from answers to the keys they are listed for
+    #[serde(skip_serializing, skip_deserializing)]
+    keys_by_word: HashMap<String, HashSet<String>>,
+    // Index from sorted letters to the known words with those letters
+    #[serde(skip_serializing, skip_deserializing)]
+    anagrams: HashMap<String, HashSet<String>>,
+    // Offer answers to related keys for keys without candidates
+    #[serde(skip_serializing, skip_deserializing)]
+    pub synonyms: bool,
+    // Offer inflected forms of answers for keys without candidates
+    #[serde(skip_serializing, skip_deserializing)]
+    pub inflect: bool,
+    // Add the words of solved boards
+    #[serde(skip_serializing, skip_deserializing)]
+    pub learn: bool,
+    // Candidates returned by lookup which are not listed for the key
+    #[serde(skip_serializing, skip_deserializing)]
+    derived: HashMap<String, HashMap<String, Derivation>>,
+    // Changes not yet written to the database
+    #[serde(skip_serializing, skip_deserializing)]
+    changes: Vec<Change>,
+    // Keys which have been read from the database. They are not read
+    // again, so that changed keys are kept.
+    #[cfg(feature = "sqlite")]
+    #[serde(skip_serializing, skip_deserializing)]
+    read_keys: HashSet<String>,
+    // All entries of the database have been read
+    #[cfg(feature = "sqlite")]
+    #[serde(skip_serializing, skip_deserializing)]
+    all_read: bool,
+    #[cfg(feature = "sqlite")]
+    #[serde(skip_serializing, skip_deserializing)]
+    store: Option<SqliteStore>,
+    // Number of backups kept when storing the dictionary
+    #[serde(skip_serializing, skip_deserializing)]
+    #[serde(default = "default_backups")]
+    pub backups: usize,
+}
+
+// Version of the dictionary file format written by this version of kryss
+const FORMAT_VERSION: u32 = 2;
+
+// Migrations of older file formats. The migration at index n converts a
+// file of version n to version n + 1.
+const MIGRATIONS: [fn(&mut JsonMap<String, JsonValue>); 2] = [
+    migrate_v0,
+    migrate_v1,
+];
+
+// Version 0 is the unversioned format, where only the words are required.
+// The other sections are added, and keys and words are normalized after
+// reading the file.
+fn migrate_v0(value: &mut JsonMap<String, JsonValue>) {
+    for section in ["frequencies", "choices", "display", "aliases",
+                    "provenance"] {
+        value.entry(section).or_insert(JsonValue::Object(JsonMap::new()));
+    }
 }
 
-impl Dictionary {
-    pub fn new() -> Self {
-        Self {
-            words: HashMap::new(),
-            changed: false,
-            filename: "".to_string(),
+// Version 1 stores the display forms by word. They are stored for each key
+// which lists the word.
+fn migrate_v1(value: &mut JsonMap<String, JsonValue>) {
+    let words = value.get("words").and_then(|w| w.as_object()).cloned()
+        .unwrap_or_default();
+    let display = value.get("display").and_then(|d| d.as_object()).cloned()
+        .unwrap_or_default();
+
+    value.insert("display".to_string(),
+                 JsonValue::Object(display_by_key(&words, &display)));
+}
+
+// Display forms stored by word, as in version 1, for each key which lists
+// the word
+fn display_by_key(words: &JsonMap<String, JsonValue>,
+                  display: &JsonMap<String, JsonValue>)
+                  -> JsonMap<String, JsonValue> {
+    let mut ret = JsonMap::new();
+
+    for (key, whash) in words {
+        let mut forms = JsonMap::new();
+
+        for wlist in whash.as_object().into_iter().flat_map(|h| h.values()) {
+            for w in wlist.as_array().into_iter().flatten() {
+                let w = match w.as_str() {
+                    Some(w) => normalize_word(w),
+                    None => continue,
+                };
+
+                if let Some(form) = display.get(&w) {
+                    forms.insert(w, form.clone());
+                }
+            }
+        }
+
+        if !forms.is_empty() {
+            ret.insert(key.clone(), JsonValue::Object(forms));
+        }
+    }
+
+    return ret;
+}
+
+// The version field of a dictionary file, read without the rest of it
+#[derive(Deserialize)]
+struct FormatHeader {
+    #[serde(default)]
+    version: u32,
+}
+
+// Dictionary files with these suffixes are sqlite databases
+const SQLITE_SUFFIXES: [&str; 3] = [".db", ".sqlite", ".sqlite3"];
+
+fn is_sqlite_file(fname: &str) -> bool {
+    SQLITE_SUFFIXES.iter().any(|s| fname.ends_with(s))
+}
+
+impl Dictionary {
+    pub fn new() -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            words: HashMap::new(),
+            frequencies: HashMap::new(),
+            choices: HashMap::new(),
+            display: HashMap::new(),
+            aliases: HashMap::new(),
+            provenance: HashMap::new(),
+            changed: false,
+            dirty_since_snapshot: false,
+            filename: "".to_string(),
+            autosave: false,
+            last_autosave: None,
+            observer: None,
+            alphabet: None,
+            transliterate: false,
+            fuzzy_keys: false,
+            resolved: HashMap::new(),
+            keys_by_word: HashMap::new(),
+            anagrams: HashMap::new(),
+            synonyms: true,
+            inflect: true,
+            learn: true,
+            derived: HashMap::new(),
+            changes: vec!(),
+            #[cfg(feature = "sqlite")]
+            read_keys: HashSet::new(),
+            #[cfg(feature = "sqlite")]
+            all_read: false,
+            #[cfg(feature = "sqlite")]
+            store: None,
+            backups: default_backups(),
+        }
+    }
+
+    // Read a dictionary file, or start an empty dictionary which is stored
+    // under the file name if the file is missing
+    pub fn from_file_or_new(file: &str) -> Result<Self, String> {
+        if fs::metadata(file).is_err() {
+            let mut ret = Self::new();
+            ret.filename = file.to_string();
+            return Ok(ret);
+        }
+
+        return Self::from_file(file);
+    }
+
+    // Read a dictionary file. A missing file is an error.
+    pub fn from_file(file: &str) -> Result<Self, String> {
+        if is_sqlite_file(file) {
+            // Opening a missing database would create it
+            fs::metadata(file)
+                .map_err(|e| format!("Unable to read {}: {}", file, e))?;
+
+            return Self::from_sqlite(file);
+        }
+
+        let data = fs::read_to_string(&file)
+            .map_err(|e| format!("Unable to read {}: {}", file, e))?;
+
+        let header: FormatHeader = serde_json::from_str(&data)
+            .map_err(|e| format!("Unable to parse {}: {}", file, e))?;
+
+        if header.version > FORMAT_VERSION {
+            return Err(format!("{} has format version {}, which is newer \
+                                than the supported version {}. Upgrade \
+                                kryss to read it.", file, header.version,
+                               FORMAT_VERSION));
+        }
+
+        if header.version == FORMAT_VERSION {
+            let ret: Self = serde_json::from_str(&data)
+                .map_err(|e| format!("Unable to parse {}: {}", file, e))?;
+
+            return Ok(ret.loaded(file));
+        }
+
+        let mut ret = Self::migrate(&data, header.version)
+            .map_err(|e| format!("Unable to migrate {}: {}", file, e))?;
+        ret.normalize_entries();
+        let mut ret = ret.loaded(file);
+
+        // Store the file in the current format
+        ret.changed = true;
+
+        return Ok(ret);
+    }
+
+    // Read a file of an older format version
+    fn migrate(data: &str, version: u32) -> Result<Self, String> {
+        let mut value: JsonMap<String, JsonValue> =
+            serde_json::from_str(data).map_err(|e| e.to_string())?;
+
+        for m in &MIGRATIONS[version as usize..] {
+            m(&mut value);
+        }
+
+        value.insert("version".to_string(), JsonValue::from(FORMAT_VERSION));
+
+        serde_json::from_value(JsonValue::Object(value))
+            .map_err(|e| e.to_string())
+    }
+
+    // Check if readable entries may be recovered from a file which can't be
+    // read. Databases and files of newer format versions are not corrupt.
+    pub fn is_recoverable(file: &str) -> bool {
+        if is_sqlite_file(file) {
+            return false;
+        }
+
+        match fs::read_to_string(file) {
+            Ok(data) => match serde_json::from_str::<FormatHeader>(&data) {
+                Ok(header) => header.version <= FORMAT_VERSION,
+                Err(_) => true,
+            },
+            Err(_) => false,
+        }
+    }
+
+    // Read the entries which are readable from a corrupt dictionary file.
+    // Returns the dictionary and the number of recovered keys. The
+    // dictionary is marked as changed, so that the file is repaired when
+    // the dictionary is stored.
+    pub fn recover_from_file(file: &str) -> Result<(Self, usize), String> {
+        let data = fs::read(&file)
+            .map_err(|e| format!("Unable to read {}: {}", file, e))?;
+        let data = String::from_utf8_lossy(&data);
+
+        let mut value = JsonMap::new();
+        value.insert("words".to_string(), JsonValue::Object(
+            recover_json_section::<HashMap<usize, Vec<String>>>(
+                &data, "words")));
+        value.insert("frequencies".to_string(), JsonValue::Object(
+            recover_json_section::<u64>(&data, "frequencies")));
+        value.insert("choices".to_string(), JsonValue::Object(
+            recover_json_section::<HashMap<String, u32>>(
+                &data, "choices")));
+        // Display forms of version 1 files are stored by word
+        let mut display = display_by_key(
+            value["words"].as_object().unwrap(),
+            &recover_json_section::<String>(&data, "display"));
+        for (key, forms) in recover_json_section::<HashMap<String, String>>(
+            &data, "display") {
+            display.insert(key, forms);
+        }
+        value.insert("display".to_string(), JsonValue::Object(display));
+        value.insert("aliases".to_string(), JsonValue::Object(
+            recover_json_section::<String>(&data, "aliases")));
+        value.insert("provenance".to_string(), JsonValue::Object(
+            recover_json_section::<HashMap<String, Provenance>>(
+                &data, "provenance")));
+
+        let mut ret: Self = serde_json::from_value(JsonValue::Object(value))
+            .map_err(|e| format!("Unable to recover {}: {}", file, e))?;
+        ret.normalize_entries();
+        let mut ret = ret.loaded(file);
+        let count = ret.words.len();
+        ret.changed = true;
+
+        return Ok((ret, count));
+    }
+
+    // Prepare a dictionary read from a json file
+    fn loaded(mut self, file: &str) -> Self {
+        self.version = FORMAT_VERSION;
+        self.synonyms = true;
+        self.inflect = true;
+        self.learn = true;
+
+        self.build_indexes();
+
+        self.filename = file.to_string();
+        self.changed = false;
+
+        return self;
+    }
+
+    // Open a database. Only the aliases are read here, and the other
+    // entries are read when they are needed.
+    #[cfg(feature = "sqlite")]
+    fn from_sqlite(file: &str) -> Result<Self, String> {
+        let store = SqliteStore::open(file)?;
+        let mut ret = Self::new();
+
+        ret.aliases = store.load_aliases()?;
+        ret.store = Some(store);
+        ret.filename = file.to_string();
+
+        return Ok(ret);
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn from_sqlite(file: &str) -> Result<Self, String> {
+        Err(format!("Unable to read {}: built without sqlite support", file))
+    }
+
+    // Normalize keys and words of entries stored by earlier versions, and
+    // merge entries which turn out to be equal.
+    fn normalize_entries(&mut self) {
+        let mut words: HashMap<String, HashMap<usize, Vec<String>>> =
+            HashMap::new();
+
+        for (key, whash) in self.words.drain() {
+            let nhash = words.entry(normalize_key(&key)).or_default();
+
+            for wlist in whash.values() {
+                for w in wlist {
+                    let nw = normalize_word(w);
+                    let list = nhash.entry(nw.chars().count()).or_default();
+
+                    if !list.contains(&nw) {
+                        list.push(nw);
+                    }
+                }
+            }
+        }
+
+        self.words = words;
+
+        let mut choices: HashMap<String, HashMap<String, u32>> =
+            HashMap::new();
+
+        for (key, chash) in self.choices.drain() {
+            let nhash = choices.entry(normalize_key(&key)).or_default();
+
+            for (w, n) in chash {
+                *nhash.entry(normalize_word(&w)).or_insert(0) += n;
+            }
+        }
+
+        self.choices = choices;
+
+        let mut display: HashMap<String, HashMap<String, String>> =
+            HashMap::new();
+
+        for (key, dhash) in self.display.drain() {
+            let nhash = display.entry(normalize_key(&key)).or_default();
+
+            for (w, form) in dhash {
+                nhash.insert(normalize_word(&w), form);
+            }
+        }
+
+        self.display = display;
+    }
+
+    pub fn alphabet(&self) -> &str {
+        match &self.alphabet {
+            Some(a) => a,
+            None => DEFAULT_ALPHABET,
+        }
+    }
+
+    pub fn set_alphabet(&mut self, alphabet: &str) {
+        self.alphabet = Some(normalize_word(alphabet));
+    }
+
+    pub fn in_alphabet(&self, word: &str) -> bool {
+        in_alphabet(word, self.alphabet())
+    }
+
+    // Read the recovery snapshot of a dictionary file. The dictionary is
+    // marked as changed, since the snapshot has not been stored. The
+    // snapshot of a database holds the changes which were not written.
+    pub fn from_recovery(file: &str) -> Result<Self, String> {
+        let fname = recovery_filename(file);
+
+        if is_sqlite_file(file) {
+            let data = fs::read_to_string(&fname)
+                .map_err(|e| format!("Unable to read {}: {}", fname, e))?;
+            let changes: Vec<Change> = serde_json::from_str(&data)
+                .map_err(|e| format!("Unable to parse {}: {}", fname, e))?;
+            let mut ret = Self::from_sqlite(file)?;
+
+            for c in changes {
+                ret.change(c);
+            }
+
+            ret.changed = true;
+
+            return Ok(ret);
+        }
+
+        let mut ret = Self::from_file(&fname)?;
+        ret.filename = file.to_string();
+        ret.changed = true;
+
+        return Ok(ret);
+    }
+
+    pub fn set_observer(&mut self, observer: SharedObserver) {
+        self.observer = observer;
+    }
+
+    pub fn write_recovery(&mut self) -> Result<(), String> {
+        // The entries of a database are stored already
+        let data = if self.has_store() {
+            serde_json::to_string(&self.changes).unwrap()
+        }
+        else {
+            serde_json::to_string(&self).unwrap()
+        };
+
+        self.last_autosave = Some(Instant::now());
+        write_file(&recovery_filename(&self.filename), data, 0)?;
+        self.dirty_since_snapshot = false;
+
+        return Ok(());
+    }
+
+    // Back up the stored file, e.g. when the server starts, which then
+    // stores the dictionary without backups. Databases are not copied.
+    pub fn back_up(&self) -> Result<(), String> {
+        if is_sqlite_file(&self.filename) {
+            return Ok(());
+        }
+
+        rotate_backups(&self.filename, self.backups)
+    }
+
+    pub fn write_to_file(&mut self, opt_fname: Option<&str>)
+                         -> Result<(), String> {
+        let mut filename = self.filename.clone();
+
+        if let Some(fname) = opt_fname {
+            filename = fname.to_string();
+        }
+
+        if is_sqlite_file(&filename) {
+            self.write_sqlite(&filename)?;
+
+            // The snapshot holds the changes which have now been written
+            remove_recovery(&filename);
+        }
+        else {
+            self.read_all()?;
+
+            let data = serde_json::to_string(&self).unwrap();
+            write_file(&filename, data, self.backups)?;
+
+            // All entries have been read, and are stored in the file
+            #[cfg(feature = "sqlite")]
+            { self.store = None; }
+        }
+
+        self.changes.clear();
+        self.changed = false;
+        self.dirty_since_snapshot = false;
+        self.filename = filename;
+
+        return Ok(());
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn has_store(&self) -> bool {
+        self.store.is_some()
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn has_store(&self) -> bool {
+        false
+    }
+
+    // Write the changes to the database. All entries are written when the
+    // dictionary is stored into another database.
+    #[cfg(feature = "sqlite")]
+    fn write_sqlite(&mut self, filename: &str) -> Result<(), String> {
+        if self.store.is_some() && filename == self.filename {
+            return self.store.as_mut().unwrap().write(&self.changes);
+        }
+
+        self.read_all()?;
+
+        let mut store = SqliteStore::open(filename)?;
+        store.write(&self.all_changes())?;
+
+        // Keys of the other database are read when they are needed
+        self.store = Some(store);
+        self.read_keys = self.words.keys().cloned().collect();
+        self.all_read = false;
+
+        return Ok(());
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn write_sqlite(&mut self, filename: &str) -> Result<(), String> {
+        Err(format!("Unable to write {}: built without sqlite support",
+                    filename))
+    }
+
+    // Changes which add all entries of the dictionary
+    #[cfg(feature = "sqlite")]
+    fn all_changes(&self) -> Vec<Change> {
+        let mut ret = vec!();
+
+        for (key, whash) in &self.words {
+            ret.push(Change::AddKey(key.clone()));
+
+            let mut lengths: Vec<&usize> = whash.keys().collect();
+            lengths.sort();
+
+            for len in lengths {
+                for w in &whash[len] {
+                    ret.push(Change::AddWord {
+                        key: key.clone(),
+                        word: w.clone(),
+                    });
+                }
+            }
+        }
+
+        for (key, phash) in &self.provenance {
+            for (w, p) in phash {
+                ret.push(Change::Provenance {
+                    key: key.clone(),
+                    word: w.clone(),
+                    provenance: Some(p.clone()),
+                });
+            }
+        }
+
+        for (key, chash) in &self.choices {
+            for (w, n) in chash {
+                ret.push(Change::Choice {
+                    key: key.clone(),
+                    word: w.clone(),
+                    count: *n,
+                });
+            }
+        }
+
+        for (key, target) in &self.aliases {
+            ret.push(Change::Alias {
+                key: key.clone(),
+                target: Some(target.clone()),
+            });
+        }
+
+        for (key, dhash) in &self.display {
+            for (w, form) in dhash {
+                ret.push(Change::Display {
+                    key: key.clone(),
+                    word: w.clone(),
+                    form: form.clone(),
+                });
+            }
+        }
+
+        for (w, n) in &self.frequencies {
+            ret.push(Change::Frequency { word: w.clone(), count: *n });
+        }
+
+        return ret;
+    }
+
+    // Read the entries of a key from the database, unless they have been
+    // read already. Returns whether the key is known.
+    #[cfg(feature = "sqlite")]
+    fn read_stored_key(&mut self, key: &str) -> bool {
+        if self.store.is_none() || self.all_read ||
+            !self.read_keys.insert(key.to_string()) {
+                return self.words.contains_key(key);
+            }
+
+        match self.store.as_ref().unwrap().load_key(key) {
+            Ok(Some(entries)) => {
+                self.words.insert(key.to_string(), entries.words);
+
+                if !entries.choices.is_empty() {
+                    self.choices.insert(key.to_string(), entries.choices);
+                }
+
+                if !entries.provenance.is_empty() {
+                    self.provenance.insert(key.to_string(),
+                                           entries.provenance);
+                }
+
+                if !entries.display.is_empty() {
+                    self.display.insert(key.to_string(), entries.display);
+                }
+
+                self.index_key(key);
+            },
+            Ok(None) => { },
+            Err(e) => {
+                self.read_keys.remove(key);
+                notify(&self.observer, BoardEvent::StoreFailed(e));
+            },
+        }
+
+        return self.words.contains_key(key);
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn read_stored_key(&mut self, key: &str) -> bool {
+        self.words.contains_key(key)
+    }
+
+    // Read the entries of a key, if they are stored in a database
+    pub fn load_key(&mut self, key: &str) {
+        let key = self.canonical_key(key);
+        self.read_stored_key(&key);
+    }
+
+    // Read all entries of the database, for commands which use the whole
+    // dictionary. Keys which have been read already are kept.
+    #[cfg(feature = "sqlite")]
+    pub fn read_all(&mut self) -> Result<(), String> {
+        if self.all_read {
+            return Ok(());
+        }
+
+        let entries = match &self.store {
+            Some(store) => store.load()?,
+            None => return Ok(()),
+        };
+
+        for (key, whash) in entries.words {
+            if !self.read_keys.contains(&key) {
+                self.words.insert(key, whash);
+            }
+        }
+
+        for (key, chash) in entries.choices {
+            if !self.read_keys.contains(&key) {
+                self.choices.insert(key, chash);
+            }
+        }
+
+        for (key, phash) in entries.provenance {
+            if !self.read_keys.contains(&key) {
+                self.provenance.insert(key, phash);
+            }
+        }
+
+        for (key, dhash) in entries.display {
+            if !self.read_keys.contains(&key) {
+                self.display.insert(key, dhash);
+            }
+        }
+
+        for (w, n) in entries.frequencies {
+            self.frequencies.entry(w).or_insert(n);
+        }
+
+        self.build_indexes();
+        self.all_read = true;
+
+        return Ok(());
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    pub fn read_all(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    // Stored keys which have not been read
+    #[cfg(feature = "sqlite")]
+    fn stored_keys(&self, opt_word: Option<&str>) -> Vec<String> {
+        let store = match &self.store {
+            Some(store) if !self.all_read => store,
+            _ => return vec!(),
+        };
+        let result = match opt_word {
+            Some(w) => store.keys_of(w),
+            None => store.keys(),
+        };
+
+        match result {
+            Ok(keys) => keys.into_iter()
+                .filter(|k| !self.read_keys.contains(k))
+                .collect(),
+            Err(e) => {
+                notify(&self.observer, BoardEvent::StoreFailed(e));
+                vec!()
+            },
+        }
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn stored_keys(&self, _opt_word: Option<&str>) -> Vec<String> {
+        vec!()
+    }
+
+    // Display form of a word which has not been read, for the key or for
+    // any key
+    #[cfg(feature = "sqlite")]
+    fn stored_display(&self, opt_key: Option<&str>, word: &str)
+                      -> Option<String> {
+        let store = match &self.store {
+            Some(store) if !self.all_read => store,
+            _ => return None,
+        };
+
+        if opt_key.map_or(false, |k| self.read_keys.contains(k)) {
+            return None;
+        }
+
+        match store.display(opt_key, word) {
+            Ok(d) => d,
+            Err(e) => {
+                notify(&self.observer, BoardEvent::StoreFailed(e));
+                None
+            },
+        }
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn stored_display(&self, _opt_key: Option<&str>, _word: &str)
+                      -> Option<String> {
+        None
+    }
+
+    // Frequency of a word which has not been read
+    #[cfg(feature = "sqlite")]
+    fn stored_frequency(&self, word: &str) -> Option<u64> {
+        let store = match &self.store {
+            Some(store) if !self.all_read => store,
+            _ => return None,
+        };
+
+        match store.frequency(word) {
+            Ok(n) => n,
+            Err(e) => {
+                notify(&self.observer, BoardEvent::StoreFailed(e));
+                None
+            },
+        }
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn stored_frequency(&self, _word: &str) -> Option<u64> {
+        None
+    }
+
+    // Apply a change to the entries in memory
+    fn apply(&mut self, change: &Change) {
+        match change {
+            Change::AddKey(key) => {
+                self.read_stored_key(key);
+                self.words.entry(key.clone()).or_default();
+            },
+            Change::AddWord { key, word } => {
+                self.read_stored_key(key);
+
+                let words = self.words.entry(key.clone()).or_default()
+                    .entry(word.chars().count()).or_default();
+
+                if !words.contains(word) {
+                    words.push(word.clone());
+                }
+
+                self.keys_by_word.entry(word.clone()).or_default()
+                    .insert(key.clone());
+                self.index_anagram(word);
+            },
+            Change::RemoveWord { key, word } => {
+                self.read_stored_key(key);
+
+                let len = word.chars().count();
+
+                if let Some(whash) = self.words.get_mut(key) {
+                    if let Some(words) = whash.get_mut(&len) {
+                        words.retain(|w| w != word);
+
+                        if words.is_empty() {
+                            whash.remove(&len);
+                        }
+                    }
+                }
+
+                if let Some(keys) = self.keys_by_word.get_mut(word) {
+                    keys.remove(key);
+                }
+
+                if let Some(c) = self.choices.get_mut(key) {
+                    c.remove(word);
+                }
+
+                if let Some(p) = self.provenance.get_mut(key) {
+                    p.remove(word);
+                }
+
+                if let Some(d) = self.display.get_mut(key) {
+                    d.remove(word);
+                }
+            },
+            Change::RemoveKey(key) => {
+                self.read_stored_key(key);
+
+                if let Some(whash) = self.words.remove(key) {
+                    for w in whash.values().flatten() {
+                        if let Some(keys) = self.keys_by_word.get_mut(w) {
+                            keys.remove(key);
+                        }
+                    }
+                }
+
+                self.choices.remove(key);
+                self.provenance.remove(key);
+                self.display.remove(key);
+                self.derived.remove(key);
+            },
+            Change::Choice { key, word, count } => {
+                self.read_stored_key(key);
+
+                *self.choices.entry(key.clone()).or_default()
+                    .entry(word.clone()).or_insert(0) += count;
+            },
+            Change::Provenance { key, word, provenance } => {
+                self.read_stored_key(key);
+
+                let p = self.provenance.entry(key.clone()).or_default();
+
+                match provenance {
+                    Some(prov) => { p.insert(word.clone(), prov.clone()); },
+                    None => { p.remove(word); },
+                }
+            },
+            Change::Alias { key, target: Some(t) } => {
+                self.aliases.insert(key.clone(), t.clone());
+            },
+            Change::Alias { key, target: None } => {
+                self.aliases.remove(key);
+            },
+            Change::Display { key, word, form } => {
+                self.read_stored_key(key);
+
+                self.display.entry(key.clone()).or_default()
+                    .insert(word.clone(), form.clone());
+            },
+            Change::Frequency { word, count } => {
+                self.frequencies.insert(word.clone(), *count);
+                self.index_anagram(word);
+            },
+        }
+    }
+
+    // Change the entries, and remember the change if the dictionary is
+    // stored in a database
+    fn change(&mut self, change: Change) {
+        self.apply(&change);
+
+        if self.has_store() {
+            self.changes.push(change);
+        }
+
+        self.changed = true;
+        self.dirty_since_snapshot = true;
+    }
+
+    // Write a recovery snapshot if one is due. This is done after changes
+    // made while solving, e.g. web lookups and placed words, so that a
+    // long run is not lost.
+    fn autosave(&mut self) {
+        if !self.autosave || !autosave_due(self.last_autosave) {
+            return;
+        }
+
+        if let Err(e) = self.write_recovery() {
+            notify(&self.observer, BoardEvent::AutosaveFailed(e));
+        }
+    }
+
+    // Grid form of a word. Phrases are written without spaces and hyphens
+    // in the grid, and all words are in lower case NFC form.
+    pub fn normalize(word: &str) -> String {
+        normalize_word(word)
+    }
+
+    // Original form of a word for a key, as found on the web or added by
+    // the user
+    pub fn display_form(&self, key: &str, word: &str) -> String {
+        let key = &self.canonical_key(key);
+
+        match self.display.get(key).and_then(|d| d.get(word)) {
+            Some(d) => d.clone(),
+            None => self.stored_display(Some(key), word)
+                .unwrap_or_else(|| word.to_string()),
+        }
+    }
+
+    // Original form of a word shown without a key, e.g. in search results.
+    // The form for the first key listing the word is used.
+    pub fn word_display_form(&self, word: &str) -> String {
+        let mut keys: Vec<&String> = self.keys_by_word.get(word)
+            .map(|k| k.iter().collect())
+            .unwrap_or_default();
+        keys.sort();
+
+        for key in keys {
+            if let Some(d) = self.display.get(key).and_then(|d| d.get(word)) {
+                return d.clone();
+            }
+        }
+
+        return self.stored_display(None, word)
+            .unwrap_or_else(|| word.to_string());
+    }
+
+    // Normalize a word, and remember the original form for the key if it
+    // differs
+    fn register_word(&mut self, key: &str, word: &str) -> String {
+        let grid = Self::normalize(word);
+
+        if grid != word && self.display_form(key, &grid) != word {
+            self.change(Change::Display {
+                key: key.to_string(),
+                word: grid.clone(),
+                form: word.to_string(),
+            });
+        }
+
+        return grid;
+    }
+
+    pub fn add_word(&mut self, key: &str, word: &str, source: Source) {
+        let key = &self.canonical_key(key);
+        let word = &self.register_word(key, word);
+        let len = word.chars().count();
+
+        if key.find("xxxx").is_some() {
+            notify(&self.observer,
+                   BoardEvent::UnknownKeyAdded(key.to_string()));
+        }
+
+        self.read_stored_key(key);
+
+        let known = self.words.get(key)
+            .and_then(|whash| whash.get(&len))
+            .map_or(false, |words| words.contains(word));
+
+        if known {
+            return;
+        }
+
+        self.change(Change::AddWord {
+            key: key.to_string(),
+            word: word.to_string(),
+        });
+        self.change(Change::Provenance {
+            key: key.to_string(),
+            word: word.to_string(),
+            provenance: Some(Provenance::now(source)),
+        });
+
+        notify(&self.observer, BoardEvent::WordAdded {
+            key: key.to_string(),
+            word: word.to_string(),
+        });
+        self.autosave();
+    }
+
+    // Position of the word in the list for the key. For words from the web
+    // lookup, this is the order of the search result.
+    pub fn source_rank(&self, key: &str, word: &str) -> Option<usize> {
+        let word = &normalize_word(word);
+        let len = word.chars().count();
+
+        self.words.get(&self.canonical_key(key))
+            .and_then(|whash| whash.get(&len))
+            .and_then(|words| words.iter().position(|w| w == word))
+    }
+
+    // Where a word for the key came from, if known
+    pub fn provenance(&self, key: &str, word: &str) -> Option<&Provenance> {
+        self.provenance.get(&self.canonical_key(key))
+            .and_then(|p| p.get(&normalize_word(word)))
+    }
+
+    pub fn frequency(&self, word: &str) -> u64 {
+        let word = &normalize_word(word);
+
+        match self.frequencies.get(word) {
+            Some(n) => *n,
+            None => self.stored_frequency(word).unwrap_or(0),
+        }
+    }
+
+    pub fn choices(&self, key: &str, word: &str) -> u32 {
+        self.choices.get(&self.canonical_key(key))
+            .and_then(|c| c.get(&normalize_word(word)))
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    // Remember that the user has chosen a word for the key
+    pub fn record_choice(&mut self, key: &str, word: &str) {
+        self.change(Change::Choice {
+            key: self.canonical_key(key),
+            word: normalize_word(word),
+            count: 1,
+        });
+        self.autosave();
+    }
+
+    // Import word frequencies from a text file with one word per line,
+    // optionally followed by a count. If the count is missing, the list is
+    // taken to be sorted with the most frequent word first. Returns the
+    // number of imported words.
+    pub fn import_frequencies(&mut self, fname: &str)
+                              -> Result<usize, String> {
+        let data = fs::read_to_string(fname)
+            .map_err(|e| format!("Unable to read {}: {}", fname, e))?;
+        let lines: Vec<&str> = data.lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .collect();
+        let total = lines.len() as u64;
+
+        for (i, line) in lines.iter().enumerate() {
+            let mut parts = line.split_whitespace();
+            let word = normalize_word(parts.next().unwrap());
+            let count = match parts.next() {
+                Some(c) => c.parse::<u64>()
+                    .map_err(|_| format!("Invalid count in line {}: {}",
+                                         i + 1, line))?,
+                None => total - i as u64,
+            };
+
+            self.change(Change::Frequency { word: word, count: count });
+        }
+
+        return Ok(lines.len());
+    }
+
+    fn build_indexes(&mut self) {
+        let keys: Vec<String> = self.words.keys().cloned().collect();
+        for k in keys {
+            self.index_key(&k);
+        }
+
+        let words: Vec<String> = self.frequencies.keys().cloned().collect();
+        for w in words {
+            self.index_anagram(&w);
+        }
+    }
+
+    // Add the answers of a key to the reverse index
+    fn index_key(&mut self, key: &str) {
+        if let Some(whash) = self.words.get(key) {
+            for w in whash.values().flatten() {
+                self.keys_by_word.entry(w.clone()).or_default()
+                    .insert(key.to_string());
+                self.anagrams.entry(sorted_letters(w)).or_default()
+                    .insert(w.clone());
+            }
+        }
+    }
+
+    fn index_anagram(&mut self, word: &str) {
+        self.anagrams.entry(sorted_letters(word)).or_default()
+            .insert(word.to_string());
+    }
+
+    // Check if a word is an answer to a key or in an imported word list
+    fn is_known_word(&self, word: &str) -> bool {
+        self.keys_by_word.get(word).map_or(false, |k| !k.is_empty()) ||
+            self.frequencies.contains_key(word)
+    }
+
+    // Known words using exactly the given letters, and matching the
+    // pattern if given. Sorted by word.
+    pub fn anagrams(&self, letters: &str, opt_pattern: Option<&Pattern>)
+                    -> Vec<String> {
+        let mut ret: Vec<String> = self.anagrams
+            .get(&sorted_letters(&normalize_word(letters)))
+            .map(|words| words.iter()
+                 .filter(|w| self.is_known_word(w))
+                 .filter(|w| opt_pattern.map_or(true, |p| p.matches(w)))
+                 .cloned()
+                 .collect())
+            .unwrap_or_default();
+
+        ret.sort();
+
+        return ret;
+    }
+
+    // Phrases of two or more known words, which together use exactly the
+    // given letters. With a pattern, the words are ordered so that the
+    // phrase matches it. Phrases with fewer words come first.
+    pub fn anagram_phrases(&self, letters: &str,
+                           opt_pattern: Option<&Pattern>, max_words: usize)
+                           -> Vec<Vec<String>> {
+        let letters: Vec<char> = sorted_letters(&normalize_word(letters))
+            .chars().collect();
+
+        // Letter sets of known words which can be part of a phrase
+        let mut parts: Vec<(Vec<char>, Vec<&String>)> = self.anagrams.iter()
+            .filter(|(l, _)| l.chars().count() >= MIN_PHRASE_WORD)
+            .map(|(l, words)| (l.chars().collect::<Vec<char>>(),
+                               words.iter()
+                               .filter(|w| self.is_known_word(w))
+                               .collect::<Vec<&String>>()))
+            .filter(|(l, words)| !words.is_empty() &&
+                    is_sub_multiset(l, &letters))
+            .collect();
+        parts.sort();
+
+        let mut combinations = vec!();
+        find_combinations(&parts, 0, &letters, &mut vec!(), max_words,
+                          &mut combinations);
+        combinations.sort_by_key(|c| c.len());
+
+        let mut ret = vec!();
+
+        for combination in combinations {
+            if combination.len() < 2 {
+                continue;
+            }
+
+            for phrase in expand_phrases(&parts, &combination) {
+                if ret.len() >= MAX_ANAGRAM_PHRASES {
+                    return ret;
+                }
+
+                match opt_pattern {
+                    Some(p) => {
+                        let opt_ordered = permutations(&phrase).into_iter()
+                            .find(|o| p.matches(&o.concat()));
+
+                        if let Some(ordered) = opt_ordered {
+                            ret.push(ordered);
+                        }
+                    },
+                    None => ret.push(phrase),
+                }
+            }
         }
+
+        return ret;
     }
 
-    pub fn from_file(file: &str) -> Self {
-        let data = fs::read_to_string(&file).expect("Unable to read file");
-        let mut ret: Self = serde_json::from_str(&data).unwrap();
-        ret.filename = file.to_string();
-        ret.changed = false;
+    // Keys which share at least one answer with the key
+    pub fn related_keys(&self, key: &str) -> Vec<String> {
+        let key = &self.canonical_key(key);
+        let mut ret: HashSet<String> = HashSet::new();
+
+        if let Some(whash) = self.words.get(key) {
+            for w in whash.values().flatten() {
+                if let Some(keys) = self.keys_by_word.get(w) {
+                    ret.extend(keys.iter().filter(|k| *k != key).cloned());
+                }
+
+                ret.extend(self.stored_keys(Some(w)).into_iter()
+                           .filter(|k| k != key));
+            }
+        }
+
+        let mut ret: Vec<String> = ret.into_iter().collect();
+        ret.sort();
 
         return ret;
     }
 
-    pub fn write_to_file(&mut self, opt_fname: Option<&str>) {
-        let mut filename = self.filename.clone();
+    // How a candidate returned by lookup was found, if it is not listed for
+    // the key
+    pub fn derivation(&self, key: &str, word: &str) -> Option<&Derivation> {
+        if self.source_rank(key, word).is_some() {
+            return None;
+        }
 
-        if let Some(fname) = opt_fname {
-            filename = fname.to_string();
+        self.derived.get(&self.canonical_key(key))
+            .and_then(|d| d.get(&normalize_word(word)))
+    }
+
+    // Inflected forms of the right length of the answers to the key
+    fn lookup_inflections(&mut self, key: &str, length: usize, hint: &str)
+                          -> Vec<String> {
+        let mut ret = vec!();
+        let bases: Vec<String> = match self.words.get(key) {
+            Some(whash) => whash.values().flatten().cloned().collect(),
+            None => vec!(),
+        };
+
+        for base in bases {
+            for w in inflections(&base) {
+                if w.chars().count() != length || !matches_hint(&w, hint) ||
+                    !self.in_alphabet(&w) || ret.contains(&w) {
+                        continue;
+                    }
+
+                self.derived.entry(key.to_string()).or_default()
+                    .insert(w.clone(), Derivation::Inflection(base.clone()));
+                ret.push(w);
+            }
         }
 
-        let data = serde_json::to_string(&self).unwrap();
-        fs::write(&filename, data).expect("Unable to write file");
+        return ret;
+    }
 
-        self.changed = false;
-        self.filename = filename;
+    // Answers of the right length to keys related to the key (second order
+    // synonyms)
+    fn lookup_synonyms(&mut self, key: &str, length: usize, hint: &str)
+                       -> Vec<String> {
+        let mut ret = vec!();
+
+        for rk in self.related_keys(key) {
+            self.read_stored_key(&rk);
+
+            let opt_wlist = self.words.get(&rk)
+                .and_then(|whash| whash.get(&length));
+
+            if let Some(wlist) = opt_wlist {
+                for w in wlist {
+                    if !matches_hint(w, hint) || !self.in_alphabet(w) ||
+                        ret.contains(w) {
+                            continue;
+                        }
+
+                    self.derived.entry(key.to_string()).or_default()
+                        .insert(w.clone(), Derivation::Synonym(rk.clone()));
+                    ret.push(w.clone());
+                }
+            }
+        }
+
+        return ret;
     }
 
-    pub fn add_word(&mut self, key: &str, word: &str) {
+    // Normalized key, with aliases and keys resolved by fuzzy matching
+    // replaced by the key used for them
+    fn canonical_key(&self, key: &str) -> String {
+        let mut key = normalize_key(key);
+        let mut seen = HashSet::new();
+
+        if let Some(k) = self.resolved.get(&key) {
+            key = k.clone();
+        }
+
+        while let Some(k) = self.aliases.get(&key) {
+            if !seen.insert(key.clone()) {
+                break;
+            }
+
+            key = k.clone();
+        }
+
+        return key;
+    }
+
+    // Remove a word from a key
+    pub fn remove_word(&mut self, key: &str, word: &str)
+                       -> Result<(), String> {
+        let key = &self.canonical_key(key);
+        let word = &normalize_word(word);
         let len = word.chars().count();
 
-        if key.find("xxxx").is_some() {
-            println!("Don't add unknown word {}", key);
+        self.read_stored_key(key);
+
+        let known = self.words.get(key)
+            .and_then(|whash| whash.get(&len))
+            .map_or(false, |words| words.contains(word));
+
+        if !known {
+            return Err(format!("No word {} for key {}", word, key));
         }
 
-        if let Some(whash) = self.words.get_mut(key) {
-            if let Some(words) = whash.get_mut(&len) {
-                // Key exists for the word length. First, check if word
-                // is already registered.
-                for w in &mut *words {
-                    if w == word {
-                        return;
-                    }
+        self.change(Change::RemoveWord {
+            key: key.clone(),
+            word: word.clone(),
+        });
+
+        return Ok(());
+    }
+
+    // Remove a key with all its words. The key will be looked up from the
+    // web again when it is needed.
+    pub fn forget_key(&mut self, key: &str) -> Result<(), String> {
+        let key = &normalize_key(key);
+
+        if self.aliases.contains_key(key) {
+            self.change(Change::Alias { key: key.clone(), target: None });
+            return Ok(());
+        }
+
+        if !self.read_stored_key(key) {
+            return Err(format!("Unknown key {}", key));
+        }
+
+        self.change(Change::RemoveKey(key.clone()));
+
+        return Ok(());
+    }
+
+    // Let a key use the entries of another key. Entries already stored for
+    // the key are moved to the other key, where the other key doesn't have
+    // them already.
+    pub fn alias_key(&mut self, key: &str, other: &str)
+                     -> Result<(), String> {
+        let key = &normalize_key(key);
+        let other = &self.canonical_key(other);
+
+        if key == other {
+            return Err(format!("Key {} can't be an alias of itself", key));
+        }
+
+        if self.read_stored_key(key) {
+            let whash = self.words[key].clone();
+            let mut phash = self.provenance.get(key).cloned()
+                .unwrap_or_default();
+            let dhash = self.display.get(key).cloned().unwrap_or_default();
+
+            self.change(Change::RemoveKey(key.clone()));
+
+            for w in whash.into_values().flatten() {
+                // The entry of a word which the other key has is kept
+                if self.source_rank(other, &w).is_some() {
+                    continue;
                 }
 
-                // Word is not registered. Add it
-                println!("Pushing word to dictionary");
-                words.push(word.to_string());
+                self.add_word(other, dhash.get(&w).unwrap_or(&w),
+                              Source::Manual);
+
+                // The moved words keep their source
+                self.change(Change::Provenance {
+                    key: other.clone(),
+                    word: w.clone(),
+                    provenance: phash.remove(&w),
+                });
+            }
+        }
+
+        self.derived.remove(key);
+        self.resolved.remove(key);
+        self.change(Change::Alias {
+            key: key.clone(),
+            target: Some(other.clone()),
+        });
+
+        return Ok(());
+    }
+
+    // Key which the key is an alias of, if any
+    pub fn alias_of(&self, key: &str) -> Option<String> {
+        let key = &normalize_key(key);
+
+        if self.aliases.contains_key(key) {
+            return Some(self.canonical_key(key));
+        }
+
+        return None;
+    }
+
+    // Words stored for a key, sorted by length
+    pub fn entries(&self, key: &str) -> Option<Vec<(usize, Vec<String>)>> {
+        let whash = self.words.get(&self.canonical_key(key))?;
+        let mut ret: Vec<(usize, Vec<String>)> = whash.iter()
+            .map(|(l, w)| (*l, w.clone()))
+            .collect();
+
+        ret.sort_by_key(|(l, _)| *l);
+
+        return Some(ret);
+    }
+
+    pub fn stats(&self) -> DictionaryStats {
+        DictionaryStats {
+            keys: self.words.len(),
+            words: self.words.values()
+                .map(|whash| whash.values().map(|w| w.len()).sum::<usize>())
+                .sum(),
+            empty_keys: self.words.values()
+                .filter(|whash| whash.values().all(|w| w.is_empty()))
+                .count(),
+            distinct_words: self.keys_by_word.values()
+                .filter(|keys| !keys.is_empty())
+                .count(),
+            aliases: self.aliases.len(),
+            phrases: self.display.values().map(|d| d.len()).sum(),
+            frequencies: self.frequencies.len(),
+        }
+    }
+
+    // Add the entries of another dictionary. Words missing for a key are
+    // appended after the words already known, and choice counts are added.
+    // Entries which differ are reported as conflicts.
+    pub fn merge(&mut self, other: &Dictionary) -> Vec<MergeConflict> {
+        let mut conflicts = vec!();
+
+        let mut keys: Vec<&String> = other.aliases.keys().collect();
+        keys.sort();
+
+        for key in keys {
+            let theirs = other.canonical_key(key);
+            let ours = self.alias_of(key);
+
+            if ours.as_ref() == Some(&theirs) {
+                continue;
+            }
+
+            // A key with words can't become an alias without losing them
+            let has_words = self.words.get(key)
+                .map_or(false, |whash| !whash.is_empty());
+
+            if ours.is_some() || has_words {
+                conflicts.push(MergeConflict::Alias {
+                    key: key.clone(),
+                    ours: ours,
+                    theirs: Some(theirs),
+                });
             }
             else {
-                whash.insert(len, vec![word.to_string()]);
+                // An empty key is replaced by the alias
+                if self.words.contains_key(key) {
+                    self.change(Change::RemoveKey(key.clone()));
+                }
+
+                self.change(Change::Alias {
+                    key: key.clone(),
+                    target: Some(theirs),
+                });
             }
         }
-        else {
-            let mut whash = HashMap::new();
-            whash.insert(len, vec![word.to_string()]);
-            self.words.insert(key.to_string(), whash);
+
+        let mut keys: Vec<&String> = other.words.keys().collect();
+        keys.sort();
+
+        for okey in keys {
+            let key = self.canonical_key(okey);
+
+            if other.words[okey].is_empty() {
+                continue;
+            }
+
+            if !self.read_stored_key(&key) {
+                self.change(Change::AddKey(key.clone()));
+            }
+
+            let mut lengths: Vec<&usize> = other.words[okey].keys().collect();
+            lengths.sort();
+
+            for len in lengths {
+                let theirs = &other.words[okey][len];
+                let words = self.words[&key].get(len).cloned()
+                    .unwrap_or_default();
+
+                for w in theirs {
+                    if !words.contains(w) {
+                        self.change(Change::AddWord {
+                            key: key.clone(),
+                            word: w.clone(),
+                        });
+
+                        if let Some(p) = other.provenance(okey, w) {
+                            self.change(Change::Provenance {
+                                key: key.clone(),
+                                word: w.clone(),
+                                provenance: Some(p.clone()),
+                            });
+                        }
+                    }
+                }
+            }
         }
 
-        self.changed = true;
+        let mut keys: Vec<&String> = other.display.keys().collect();
+        keys.sort();
+
+        for okey in keys {
+            let key = self.canonical_key(okey);
+            let mut words: Vec<&String> = other.display[okey].keys().collect();
+            words.sort();
+
+            for w in words {
+                let theirs = &other.display[okey][w];
+                let ours = self.display.get(&key).and_then(|d| d.get(w))
+                    .cloned();
+
+                match ours {
+                    Some(ours) if &ours != theirs => {
+                        conflicts.push(MergeConflict::Display {
+                            key: key.clone(),
+                            word: w.clone(),
+                            ours: ours,
+                            theirs: theirs.clone(),
+                        });
+                    },
+                    Some(_) => { },
+                    None => {
+                        self.change(Change::Display {
+                            key: key.clone(),
+                            word: w.clone(),
+                            form: theirs.clone(),
+                        });
+                    },
+                }
+            }
+        }
+
+        // The highest choice count is kept rather than adding them, so
+        // that merging the same file again doesn't count its choices twice
+        for (key, chash) in &other.choices {
+            let key = self.canonical_key(key);
+
+            for (w, n) in chash {
+                let ours = self.choices(&key, w);
+
+                if *n > ours {
+                    self.change(Change::Choice {
+                        key: key.clone(),
+                        word: w.clone(),
+                        count: *n - ours,
+                    });
+                }
+            }
+        }
+
+        // Frequencies are from the same kind of corpus, so the highest
+        // count is kept rather than adding them
+        for (w, n) in &other.frequencies {
+            if *n > self.frequency(w) {
+                self.change(Change::Frequency { word: w.clone(), count: *n });
+            }
+        }
+
+        return conflicts;
     }
 
-    fn lookup_from_gratiskryss(&mut self, key: &str) {
-        if key.find("xxxx").is_some() {
-            println!("Skip looking up unknown word {}", key);
-            return;
+    // Keys whose words differ from the words in another dictionary, sorted
+    // by key
+    pub fn diff(&self, other: &Dictionary) -> Vec<KeyDiff> {
+        let mut keys: HashSet<&String> = self.words.keys().collect();
+        keys.extend(other.words.keys());
+
+        let mut keys: Vec<&String> = keys.into_iter().collect();
+        keys.sort();
+
+        let words = |dict: &Dictionary, key: &str| -> HashSet<String> {
+            dict.words.get(key)
+                .map(|whash| whash.values().flatten().cloned().collect())
+                .unwrap_or_default()
+        };
+
+        let mut ret = vec!();
+
+        for key in keys {
+            let ours = words(self, key);
+            let theirs = words(other, key);
+            let mut removed: Vec<String> =
+                ours.difference(&theirs).cloned().collect();
+            let mut added: Vec<String> =
+                theirs.difference(&ours).cloned().collect();
+
+            if removed.is_empty() && added.is_empty() {
+                continue;
+            }
+
+            removed.sort();
+            added.sort();
+
+            ret.push(KeyDiff {
+                key: key.clone(),
+                removed: removed,
+                added: added,
+            });
         }
 
-        println!("Looking up {} from gratiskryssord", key);
-        let mut words: HashMap<usize, Vec<String>> = HashMap::new();
+        return ret;
+    }
 
-        if key.find("xxxx").is_some() {
-            // Don't look up unknown word
-            return;
+    // Words matching the pattern among the answers to all keys and the
+    // words of imported word lists, with the keys they are listed for.
+    // Sorted by word.
+    pub fn search(&self, pattern: &Pattern) -> Vec<(String, Vec<String>)> {
+        let length = pattern.length();
+        let mut words: HashSet<&String> = self.keys_by_word.keys().collect();
+        words.extend(self.frequencies.keys());
+
+        let mut ret = vec!();
+
+        for w in words {
+            if length.map_or(false, |l| w.chars().count() != l) ||
+                !pattern.matches(w) {
+                    continue;
+                }
+
+            let mut keys: Vec<String> = self.keys_by_word.get(w)
+                .map(|k| k.iter().cloned().collect())
+                .unwrap_or_default();
+
+            if keys.is_empty() && !self.frequencies.contains_key(w) {
+                continue;
+            }
+
+            keys.sort();
+            ret.push((w.clone(), keys));
+        }
+
+        ret.sort();
+
+        return ret;
+    }
+
+    // Known keys within the given edit distance of the key, closest first.
+    // Whitespace and diacritics are ignored.
+    pub fn similar_keys(&self, key: &str, max_distance: usize)
+                        -> Vec<(String, usize)> {
+        let folded = fold_key(key);
+        let mut keys: Vec<String> = self.words.keys().cloned().collect();
+        keys.extend(self.stored_keys(None));
+
+        let mut ret: Vec<(String, usize)> = keys.into_iter()
+            .map(|k| {
+                let d = edit_distance(&folded, &fold_key(&k));
+                (k, d)
+            })
+            .filter(|(_, d)| *d <= max_distance)
+            .collect();
+
+        ret.sort_by(|(k1, d1), (k2, d2)| d1.cmp(d2).then(k1.cmp(k2)));
+
+        return ret;
+    }
+
+    // Edit distance accepted for a typo in a key of the given length
+    fn typo_distance(key: &str) -> usize {
+        if fold_key(key).chars().count() <= 6 { 1 } else { 2 }
+    }
+
+    // Find the key to use for an unknown key. Close known keys are either
+    // used directly, if fuzzy_keys is set and there is a single closest
+    // key, or proposed to the user.
+    fn resolve_key(&mut self, key: &str) -> String {
+        if self.read_stored_key(key) || key.find("xxxx").is_some() ||
+            fold_key(key).chars().count() < MIN_FUZZY_KEY {
+            return key.to_string();
+        }
+
+        if let Some(k) = self.resolved.get(key) {
+            return k.clone();
+        }
+
+        let similar = self.similar_keys(key, Self::typo_distance(key));
+
+        if similar.is_empty() {
+            return key.to_string();
+        }
+
+        let unique = similar.len() == 1 || similar[0].1 < similar[1].1;
+
+        if self.fuzzy_keys && unique {
+            let used = similar[0].0.clone();
+
+            notify(&self.observer, BoardEvent::FuzzyKeyUsed {
+                key: key.to_string(),
+                used: used.clone(),
+            });
+            self.resolved.insert(key.to_string(), used.clone());
+
+            return used;
         }
 
+        notify(&self.observer, BoardEvent::SimilarKeys {
+            key: key.to_string(),
+            similar: similar.into_iter().map(|(k, _)| k).collect(),
+        });
+
+        return key.to_string();
+    }
+
+    // Answers to the key from gratiskryssord, by length
+    fn fetch_gratiskryss(&mut self, key: &str)
+                         -> Result<HashMap<usize, Vec<String>>, String> {
+        let mut words: HashMap<usize, Vec<String>> = HashMap::new();
         let mut url = Url::parse("https://www.gratiskryssord.no/kryssordbok/")
-            .unwrap().join(key).unwrap();
+            .and_then(|u| u.join(key))
+            .map_err(|e| format!("Invalid key {}: {}", key, e))?;
         loop {
             let html = reqwest::blocking::get(url.as_str())
-                .unwrap().text().unwrap();
+                .and_then(|r| r.text())
+                .map_err(|e| format!("Unable to look up {}: {}", key, e))?;
             let package = parse_html(&html);
             let doc = package.as_document();
-            let val = evaluate_xpath(&doc, "/html/body/section/div/div/div[1]/article/div[*]/div[*]/div[*]/div[*]/div[*]/div[*]/section/ul/li[*]/a/text()").unwrap();
+            let val = evaluate_xpath(&doc, "/html/body/section/div/div/div[1]/article/div[*]/div[*]/div[*]/div[*]/div[*]/div[*]/section/ul/li[*]/a/text()")
+                .map_err(|e| format!("Unable to parse page of {}: {}",
+                                     key, e))?;
 
             match val {
                 Value::Nodeset(ns) => {
                     for n in ns {
-                        let word = n.string_value().trim().to_string();
+                        let word = self.register_word(
+                            key, n.string_value().trim());
 
-                        if word.contains(" ") {
+                        if word.is_empty() {
                             continue;
                         }
 
@@ -120,11 +2078,13 @@ impl Dictionary {
                     }
                 }
                 _ => {
-                    panic!("Expected nodeset");
+                    return Err(format!("Unexpected page for {}", key));
                 }
             }
 
-            let val = evaluate_xpath(&doc, "/html/body/section/div/div/div[1]/article/div[3]/div/form/div[1]/div[2]/ul/li[last()]/@ng-init").unwrap();
+            let val = evaluate_xpath(&doc, "/html/body/section/div/div/div[1]/article/div[3]/div/form/div[1]/div[2]/ul/li[last()]/@ng-init")
+                .map_err(|e| format!("Unable to parse page of {}: {}",
+                                     key, e))?;
 
             match val {
                 Value::Nodeset(ns) => {
@@ -133,59 +2093,129 @@ impl Dictionary {
                     if let Some(next_node) = opt_next {
                         let next = next_node
                             .string_value()
-                            .strip_prefix("shFunc.setNextLink('").unwrap()
+                            .strip_prefix("shFunc.setNextLink('")
+                            .ok_or(format!("Unexpected page for {}", key))?
                             .replace("');", "");
 
                         if next == "" {
                             break;
                         }
-                        url = url.join(&next).unwrap();
+                        url = url.join(&next)
+                            .map_err(|e| format!("Invalid link {}: {}",
+                                                 next, e))?;
                     }
                     else {
                         break;
                     }
                 }
                 _ => {
-                    panic!("Expected string");
+                    return Err(format!("Unexpected page for {}", key));
                 }
             }
         }
 
-        self.words.insert(key.to_string(), words);
-        self.changed = true;
+        return Ok(words);
+    }
+
+    fn lookup_from_gratiskryss(&mut self, key: &str) {
+        if key.find("xxxx").is_some() {
+            notify(&self.observer,
+                   BoardEvent::LookupSkipped(key.to_string()));
+            return;
+        }
+
+        notify(&self.observer, BoardEvent::LookupStarted(key.to_string()));
+
+        // A failed lookup is not stored, so that the key is looked up again
+        let words = match self.fetch_gratiskryss(key) {
+            Ok(words) => words,
+            Err(e) => {
+                notify(&self.observer, BoardEvent::LookupFailed {
+                    key: key.to_string(),
+                    error: e,
+                });
+                return;
+            },
+        };
+
+        let prov = Provenance::now(Source::Web);
+        let mut lengths: Vec<&usize> = words.keys().collect();
+        lengths.sort();
+
+        self.change(Change::AddKey(key.to_string()));
+
+        for len in lengths {
+            for w in &words[len] {
+                self.change(Change::AddWord {
+                    key: key.to_string(),
+                    word: w.clone(),
+                });
+                self.change(Change::Provenance {
+                    key: key.to_string(),
+                    word: w.clone(),
+                    provenance: Some(prov.clone()),
+                });
+            }
+        }
+
+        self.autosave();
     }
 
     pub fn lookup(&mut self, key: &str, length: usize, opt_hint: Option<&str>)
                   -> Vec<String> {
         let hint;
+        let key = &self.resolve_key(&self.canonical_key(key));
 
         if let Some(h) = opt_hint {
-            hint = h.to_string();
+            hint = normalize_word(h);
         }
         else {
             hint = String::from_iter(vec!['.'; length]);
         }
 
-        if !self.words.contains_key(key) {
+        if !self.read_stored_key(key) {
             self.lookup_from_gratiskryss(key);
         }
 
         let mut ret = vec!();
 
-        if self.words.contains_key(key) {
-            if self.words[key].contains_key(&length) {
-                'outer: for w in &self.words[key][&length] {
-                    for (a, b) in w.chars().zip(hint.chars()) {
-                        if a != b && b != '.' {
-                            continue 'outer;
-                        }
+        if let Some(whash) = self.words.get(key) {
+            let mut words = vec!();
+
+            if self.transliterate {
+                // Words of other lengths may have the right length when
+                // transliterated
+                for wlist in whash.values() {
+                    for w in wlist {
+                        words.push(w.clone());
+                        words.extend(transliterations(w));
+                    }
+                }
+            }
+            else if let Some(wlist) = whash.get(&length) {
+                words = wlist.clone();
+            }
+
+            for w in words {
+                if w.chars().count() != length || !self.in_alphabet(&w) ||
+                    !matches_hint(&w, &hint) {
+                        continue;
                     }
 
-                    ret.push(w.to_string());
+                if !ret.contains(&w) {
+                    ret.push(w);
                 }
             }
         }
 
+        if ret.is_empty() && self.inflect {
+            ret = self.lookup_inflections(key, length, &hint);
+        }
+
+        if ret.is_empty() && self.synonyms {
+            ret = self.lookup_synonyms(key, length, &hint);
+        }
+
         return ret;
     }
 
@@ -193,3 +2223,179 @@ impl Dictionary {
         return serde_json::to_string(&self).unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json_map(data: &str) -> JsonMap<String, JsonValue> {
+        serde_json::from_str(data).unwrap()
+    }
+
+    fn dictionary(data: &str) -> Dictionary {
+        let dict: Dictionary = serde_json::from_str(data).unwrap();
+        dict.loaded("test.json")
+    }
+
+    fn words(dict: &Dictionary, key: &str, len: usize) -> Vec<String> {
+        dict.words[key][&len].clone()
+    }
+
+    #[test]
+    fn migrate_v0_adds_missing_sections() {
+        let mut value = json_map(
+            r#"{"words":{"elv":{"3":["nil"]}},"aliases":{"å":"elv"}}"#);
+        migrate_v0(&mut value);
+
+        for section in ["frequencies", "choices", "display", "provenance"] {
+            assert_eq!(value[section], JsonValue::Object(JsonMap::new()));
+        }
+
+        assert_eq!(value["aliases"]["å"], "elv");
+        assert_eq!(value["words"]["elv"]["3"][0], "nil");
+    }
+
+    #[test]
+    fn migrate_v1_stores_display_forms_by_key() {
+        let mut value = json_map(concat!(
+            r#"{"words":{"gi opp":{"5":["giseg"]},"#,
+            r#""annen":{"5":["giseg","abcde"]},"elv":{"3":["nil"]}},"#,
+            r#""display":{"giseg":"gi seg"}}"#));
+        migrate_v1(&mut value);
+
+        let display = value["display"].as_object().unwrap();
+        assert_eq!(display.len(), 2);
+        assert_eq!(display["gi opp"]["giseg"], "gi seg");
+        assert_eq!(display["annen"]["giseg"], "gi seg");
+        assert_eq!(display["annen"].as_object().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn migrates_unversioned_file() {
+        let data = concat!(r#"{"words":{"Gi  Opp":{"6":["Gi seg"]}},"#,
+                           r#""choices":{"Gi Opp":{"Giseg":2}}}"#);
+        let mut dict = Dictionary::migrate(data, 0).unwrap();
+        dict.normalize_entries();
+
+        assert_eq!(dict.version, FORMAT_VERSION);
+        assert_eq!(dict.words["gi opp"][&5], vec!("giseg".to_string()));
+        assert_eq!(dict.choices["gi opp"]["giseg"], 2);
+        assert!(dict.display.is_empty());
+        assert!(dict.aliases.is_empty());
+    }
+
+    #[test]
+    fn migrates_display_forms_of_version_1() {
+        let data = concat!(r#"{"version":1,"words":{"gi opp":"#,
+                           r#"{"5":["giseg"]}},"frequencies":{},"#,
+                           r#""choices":{},"display":{"giseg":"gi seg"},"#,
+                           r#""aliases":{},"provenance":{}}"#);
+        let mut dict = Dictionary::migrate(data, 1).unwrap();
+        dict.normalize_entries();
+
+        assert_eq!(dict.version, FORMAT_VERSION);
+        assert_eq!(dict.display["gi opp"]["giseg"], "gi seg");
+    }
+
+    #[test]
+    fn merge_keeps_the_words_of_both() {
+        let mut ours = dictionary(r#"{"words":{"elv":{"3":["nil","ob"]}}}"#);
+        let theirs = dictionary(
+            r#"{"words":{"elv":{"3":["rin","nil"]},"flod":{"4":["elva"]}}}"#);
+        let conflicts = ours.merge(&theirs);
+
+        assert!(conflicts.is_empty());
+        assert_eq!(words(&ours, "elv", 3), vec!("nil", "ob", "rin"));
+        assert_eq!(words(&ours, "flod", 4), vec!("elva"));
+        assert!(ours.changed);
+    }
+
+    #[test]
+    fn merge_reports_alias_conflicts() {
+        let mut ours = dictionary(concat!(
+            r#"{"words":{"elv":{"3":["nil"]},"flod":{"3":["nil"]}},"#,
+            r#""aliases":{"å":"elv"}}"#));
+        let theirs = dictionary(concat!(
+            r#"{"words":{"elv":{"3":["nil"]}},"#,
+            r#""aliases":{"flod":"elv","å":"bekk"}}"#));
+        let conflicts = ours.merge(&theirs);
+
+        assert_eq!(conflicts.len(), 2);
+        assert!(matches!(&conflicts[0], MergeConflict::Alias {
+            key, ours: None, theirs: Some(t) }
+            if key == "flod" && t == "elv"));
+        assert!(matches!(&conflicts[1], MergeConflict::Alias {
+            key, ours: Some(o), theirs: Some(t) }
+            if key == "å" && o == "elv" && t == "bekk"));
+        assert_eq!(ours.alias_of("flod"), None);
+        assert_eq!(ours.alias_of("å"), Some("elv".to_string()));
+    }
+
+    #[test]
+    fn merge_replaces_empty_key_by_alias() {
+        let mut ours = dictionary(
+            r#"{"words":{"elv":{"3":["nil"]},"flod":{}}}"#);
+        let theirs = dictionary(
+            r#"{"words":{"elv":{"3":["nil"]}},"aliases":{"flod":"elv"}}"#);
+        let conflicts = ours.merge(&theirs);
+
+        assert!(conflicts.is_empty());
+        assert!(!ours.words.contains_key("flod"));
+        assert_eq!(ours.alias_of("flod"), Some("elv".to_string()));
+        assert_eq!(ours.entries("flod").unwrap()[0].1, vec!("nil"));
+    }
+
+    #[test]
+    fn merge_keeps_the_highest_choice_count() {
+        let mut ours = dictionary(concat!(
+            r#"{"words":{"elv":{"3":["nil","rin"]}},"#,
+            r#""choices":{"elv":{"nil":2,"rin":5}}}"#));
+        let theirs = dictionary(concat!(
+            r#"{"words":{"elv":{"3":["nil","rin"]}},"#,
+            r#""choices":{"elv":{"nil":3,"rin":1}}}"#));
+
+        ours.merge(&theirs);
+        ours.merge(&theirs);
+
+        assert_eq!(ours.choices("elv", "nil"), 3);
+        assert_eq!(ours.choices("elv", "rin"), 5);
+    }
+
+    #[test]
+    fn merge_reports_display_conflicts() {
+        let mut ours = dictionary(concat!(
+            r#"{"words":{"gi opp":{"5":["giseg"]}},"#,
+            r#""display":{"gi opp":{"giseg":"gi seg"}}}"#));
+        let theirs = dictionary(concat!(
+            r#"{"words":{"gi opp":{"5":["giseg"]},"annen":{"5":["giseg"]}},"#,
+            r#""display":{"gi opp":{"giseg":"gis-eg"},"#,
+            r#""annen":{"giseg":"gis eg"}}}"#));
+        let conflicts = ours.merge(&theirs);
+
+        assert_eq!(conflicts.len(), 1);
+        assert!(matches!(&conflicts[0], MergeConflict::Display {
+            key, word, ours, theirs }
+            if key == "gi opp" && word == "giseg" && ours == "gi seg" &&
+               theirs == "gis-eg"));
+        assert_eq!(ours.display_form("gi opp", "giseg"), "gi seg");
+        assert_eq!(ours.display_form("annen", "giseg"), "gis eg");
+    }
+
+    #[test]
+    fn diff_lists_removed_and_added_words() {
+        let ours = dictionary(
+            r#"{"words":{"elv":{"3":["nil","rin"]},"flod":{"4":["elva"]}}}"#);
+        let theirs = dictionary(
+            r#"{"words":{"elv":{"3":["nil"],"5":["donau"]},"å":{}}}"#);
+        let diff = ours.diff(&theirs);
+
+        assert_eq!(diff.len(), 2);
+        assert_eq!(diff[0].key, "elv");
+        assert_eq!(diff[0].removed, vec!("rin"));
+        assert_eq!(diff[0].added, vec!("donau"));
+        assert_eq!(diff[1].key, "flod");
+        assert_eq!(diff[1].removed, vec!("elva"));
+        assert!(diff[1].added.is_empty());
+        assert!(ours.diff(&ours).is_empty());
+    }
+}
@@ -0,0 +1,33 @@
+use std::rc::Rc;
+
+// Events emitted by the board and the dictionary. The library types don't
+// print anything themselves. A front end which wants to follow what happens,
+// e.g. during solving or web lookups, registers an observer.
+pub enum BoardEvent {
+    Placed { index: usize, key: Option<String>, word: String },
+    Unplaced { index: usize, key: Option<String>, word: String },
+    LookupStarted(String),
+    LookupSkipped(String),
+    LookupFailed { key: String, error: String },
+    WordAdded { key: String, word: String },
+    SimilarKeys { key: String, similar: Vec<String> },
+    FuzzyKeyUsed { key: String, used: String },
+    UnknownKeyAdded(String),
+    AutosaveFailed(String),
+    StoreFailed(String),
+    // Events of the http server
+    Listening(String),
+    ResponseFailed(String),
+}
+
+pub trait Observer {
+    fn notify(&self, event: &BoardEvent);
+}
+
+pub type SharedObserver = Option<Rc<dyn Observer>>;
+
+pub fn notify(observer: &SharedObserver, event: BoardEvent) {
+    if let Some(o) = observer {
+        o.notify(&event);
+    }
+}
@@ -0,0 +1,45 @@
+// Inflection of base forms, as listed in the dictionary, for common Bokmål
+// and Nynorsk patterns. The rules are simple suffix rules, and will
+// generate forms which don't exist. They are only used to propose
+// candidates when the dictionary has none of the right length.
+
+// Suffixes added to the base form. Nouns: definite singular (bilen, boka,
+// huset), plural (biler, bilar) and definite plural (bilene, bilane, husa).
+// Verbs: present (kaster, kastar) and past tense (kastet, kasta, spiste,
+// levde). Adjectives: inflection (store, stort), comparative and
+// superlative (større, størst). Genitive (bilens).
+const SUFFIXES: &[&str] = &[
+    "en", "a", "et", "er", "ar", "ene", "ane", "e", "t", "te", "de",
+    "ere", "est", "s", "ens", "ets",
+];
+
+// Suffixes which replace a final unstressed 'e' of the base form, e.g.
+// jente -> jenta, spise -> spiste, hoppe -> hoppar.
+const E_SUFFIXES: &[&str] = &[
+    "a", "en", "et", "er", "ar", "ane", "te", "de",
+];
+
+pub fn inflections(word: &str) -> Vec<String> {
+    let mut ret = vec!();
+
+    for s in SUFFIXES {
+        ret.push(format!("{}{}", word, s));
+    }
+
+    if let Some(stem) = word.strip_suffix('e') {
+        // Words ending with 'e' only add the consonant, e.g. eple -> eplet
+        for s in ["n", "r", "t", "ne", "ns"] {
+            ret.push(format!("{}{}", word, s));
+        }
+
+        for s in E_SUFFIXES {
+            ret.push(format!("{}{}", stem, s));
+        }
+    }
+
+    ret.sort();
+    ret.dedup();
+    ret.retain(|w| w != word);
+
+    return ret;
+}
@@ -0,0 +1,103 @@
+use crate::board::Board;
+use crate::dictionary::Dictionary;
+
+use serde_json::{json, Value};
+
+// Json views of the board, shared by the json output mode of the command
+// line interface and the http server.
+
+pub fn word_json(board: &Board, a: usize) -> Value {
+    let w = &board.words[a];
+
+    let state = if w.placed {
+        "placed"
+    }
+    else if w.is_missing() {
+        "missing"
+    }
+    else if w.is_ambiguous() {
+        "ambiguous"
+    }
+    else {
+        "unique"
+    };
+
+    json!({
+        "index": a,
+        "key": w.key,
+        "orientation": w.o.to_string(),
+        "x": w.x,
+        "y": w.y,
+        "length": w.length,
+        "cells": w.cells,
+        "placed": w.placed,
+        "word": if w.placed { Some(&w.candidates[0]) } else { None },
+        "hint": board.get_hints(a),
+        "state": state,
+        "candidates": w.candidates,
+    })
+}
+
+pub fn crossings_json(board: &Board, a: usize) -> Value {
+    let mut ret = vec!();
+
+    if let Some(cr) = board.crossings.get(&a) {
+        for (b, ai, bi) in cr {
+            ret.push(json!({
+                "position": ai,
+                "crossing_position": bi,
+                "word": word_json(board, *b),
+            }));
+        }
+    }
+
+    Value::Array(ret)
+}
+
+pub fn ranked_candidates_json(board: &Board, a: usize, dict: &Dictionary)
+                              -> Value {
+    let key = board.words[a].key.clone().unwrap_or_default();
+
+    Value::Array(board.ranked_candidates(a, dict).iter()
+                 .map(|(c, s)| json!({
+                     "word": c,
+                     "derived": dict.derivation(&key, c)
+                         .map(|d| d.to_string()),
+                     "display": dict.display_form(&key, c),
+                     "provenance": dict.provenance(&key, c),
+                     "score": s.total(),
+                     "rank": s.rank,
+                     "frequency": s.frequency,
+                     "crossings": s.crossings,
+                     "choices": s.choices,
+                 }))
+                 .collect())
+}
+
+// Sources of the candidates of a word
+pub fn provenance_json(board: &Board, a: usize, dict: &Dictionary) -> Value {
+    let w = &board.words[a];
+    let key = w.key.clone().unwrap_or_default();
+
+    Value::Array(w.candidates.iter()
+                 .map(|c| json!({
+                     "word": c,
+                     "provenance": dict.provenance(&key, c),
+                 }))
+                 .collect())
+}
+
+pub fn words_json(board: &Board) -> Value {
+    Value::Array((0..board.words.len())
+                 .map(|a| word_json(board, a))
+                 .collect())
+}
+
+pub fn board_json(board: &Board) -> Value {
+    json!({
+        "state": board.state.to_string(),
+        "changed": board.changed,
+        "filename": board.filename,
+        "rows": board.rows(),
+    })
+}
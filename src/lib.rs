@@ -2,7 +2,27 @@ mod dictionary;
 mod board;
 mod word;
 mod cli;
+mod json;
+mod server;
+mod recovery;
+mod event;
+mod score;
+mod text;
+mod inflect;
+mod atomic;
+mod pattern;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 
-pub use crate::dictionary::Dictionary;
-pub use crate::board::{State, Board};
-pub use crate::cli::{KryssApp, KryssKeywordExpander};
+pub use crate::dictionary::{Dictionary, Derivation, DictionaryStats};
+pub use crate::dictionary::{MergeConflict, KeyDiff};
+pub use crate::dictionary::{Source, Provenance};
+pub use crate::board::{State, Board, Suggestion};
+pub use crate::word::{Word, Orientation};
+pub use crate::cli::{KryssApp, KryssKeywordExpander, PrintObserver};
+pub use crate::event::{BoardEvent, Observer};
+pub use crate::server::Server;
+pub use crate::score::Score;
+pub use crate::pattern::Pattern;
+pub use crate::recovery::{has_newer_recovery, remove_recovery};
+pub use crate::recovery::recovery_filename;
@@ -0,0 +1,162 @@
+use unicode_normalization::UnicodeNormalization;
+
+// Word patterns for searching the dictionary. A pattern is matched against
+// the grid form of words, where
+//   .        matches any letter
+//   [aeiou]  matches one of the letters
+//   [^aeiou] matches any letter except these
+//   *        matches any number of letters
+// and other letters match themselves. Letters are compared in lower case
+// NFC form, and spaces and hyphens outside of letter sets are ignored, as
+// in the grid form of phrases.
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Letter(char),
+    Any,
+    Set(Vec<char>, bool),
+    Star,
+}
+
+pub struct Pattern {
+    tokens: Vec<Token>,
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let letters = pattern.nfc().collect::<String>().to_lowercase();
+        let mut chars = letters.chars();
+        let mut tokens = vec!();
+
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '.' => Token::Any,
+                '*' => Token::Star,
+                '[' => {
+                    let mut set = vec!();
+                    let mut negated = false;
+                    let mut closed = false;
+
+                    while let Some(s) = chars.next() {
+                        match s {
+                            ']' => {
+                                closed = true;
+                                break;
+                            },
+                            '^' if set.is_empty() && !negated => {
+                                negated = true;
+                            },
+                            _ => set.push(s),
+                        }
+                    }
+
+                    if !closed || set.is_empty() {
+                        return Err(format!("Invalid letter set in {}",
+                                           pattern));
+                    }
+
+                    Token::Set(set, negated)
+                },
+                ']' => {
+                    return Err(format!("Unmatched ] in {}", pattern));
+                },
+                '-' => continue,
+                _ if c.is_whitespace() => continue,
+                _ => Token::Letter(c),
+            };
+
+            // Repeated stars match the same as one
+            if token == Token::Star && tokens.last() == Some(&Token::Star) {
+                continue;
+            }
+
+            tokens.push(token);
+        }
+
+        Ok(Self { tokens: tokens })
+    }
+
+    // Length of the matching words, if the pattern has no stars
+    pub fn length(&self) -> Option<usize> {
+        if self.tokens.contains(&Token::Star) {
+            return None;
+        }
+
+        Some(self.tokens.len())
+    }
+
+    pub fn matches(&self, word: &str) -> bool {
+        let word: Vec<char> = word.chars().collect();
+
+        Self::matches_from(&self.tokens, &word)
+    }
+
+    fn matches_from(tokens: &[Token], word: &[char]) -> bool {
+        match tokens.first() {
+            None => word.is_empty(),
+            Some(Token::Star) => {
+                (0..=word.len())
+                    .any(|i| Self::matches_from(&tokens[1..], &word[i..]))
+            },
+            Some(t) => {
+                let c = match word.first() {
+                    Some(c) => c,
+                    None => return false,
+                };
+
+                let ok = match t {
+                    Token::Letter(l) => l == c,
+                    Token::Any => true,
+                    Token::Set(set, negated) => set.contains(c) != *negated,
+                    Token::Star => unreachable!(),
+                };
+
+                ok && Self::matches_from(&tokens[1..], &word[1..])
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, word: &str) -> bool {
+        Pattern::parse(pattern).unwrap().matches(word)
+    }
+
+    #[test]
+    fn letters_and_wildcards() {
+        assert!(matches("s.[aeiou]*", "skuta"));
+        assert!(matches("s.[aeiou]*", "sta"));
+        assert!(!matches("s.[aeiou]*", "st"));
+        assert!(!matches("s.[aeiou]*", "skrue"));
+        assert!(matches("[^aeiou]a", "ba"));
+        assert!(!matches("[^aeiou]a", "ea"));
+        assert!(matches("**e", "e"));
+    }
+
+    #[test]
+    fn letters_are_normalized() {
+        assert!(matches("ÅL", "ål"));
+        assert!(matches("a\u{30a}l", "ål"));
+        assert!(matches("[ÆØÅ].", "øy"));
+    }
+
+    #[test]
+    fn phrase_separators_are_ignored() {
+        assert!(matches("gi seg", "giseg"));
+        assert!(matches("gi-.eg", "giseg"));
+        assert_eq!(Pattern::parse("à la .*").unwrap().length(), None);
+        assert_eq!(Pattern::parse("à la ..").unwrap().length(), Some(5));
+    }
+
+    #[test]
+    fn syntax_is_kept() {
+        assert!(matches("[^-]", "a"));
+        assert!(!matches("[- ]", "a"));
+        assert!(Pattern::parse("[ab").is_err());
+        assert!(Pattern::parse("[]").is_err());
+        assert!(Pattern::parse("a]").is_err());
+    }
+}
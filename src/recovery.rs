@@ -0,0 +1,218 @@
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+use serde::de::DeserializeOwned;
+use serde_json::{Deserializer, Map, Value};
+
+// Recovery snapshots are written next to the file they belong to, with a
+// .recovery suffix added to the file name.
+
+pub fn recovery_filename(fname: &str) -> String {
+    format!("{}.recovery", fname)
+}
+
+// Minimum time between the snapshots written while a command changes the
+// board or dictionary
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+// Check if a periodic snapshot is due, given the time of the last one
+pub fn autosave_due(last: Option<Instant>) -> bool {
+    match last {
+        Some(t) => t.elapsed() >= AUTOSAVE_INTERVAL,
+        None => true,
+    }
+}
+
+fn modified(fname: &str) -> Option<SystemTime> {
+    fs::metadata(fname).and_then(|m| m.modified()).ok()
+}
+
+// Check if there is a recovery snapshot which is newer than the file
+// itself (or the file is missing).
+pub fn has_newer_recovery(fname: &str) -> bool {
+    match (modified(&recovery_filename(fname)), modified(fname)) {
+        (Some(r), Some(f)) => r > f,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+pub fn remove_recovery(fname: &str) {
+    let _ = fs::remove_file(recovery_filename(fname));
+}
+
+// Entries of a top-level json object which can be read from a partially
+// corrupt file, e.g. one truncated by a crash. Each entry is parsed on its
+// own, and unreadable entries are skipped. Only entries whose values can
+// be read as T are kept.
+pub fn recover_json_section<T: DeserializeOwned>(data: &str, name: &str)
+                                                 -> Map<String, Value> {
+    let mut ret = Map::new();
+    let start = match section_start(data, name) {
+        Some(p) => p,
+        None => return ret,
+    };
+    let mut pos = start;
+
+    while pos < data.len() {
+        let rest = &data[pos..];
+
+        if rest.trim_start().starts_with('}') {
+            break;
+        }
+
+        match read_entry(rest) {
+            Some((key, value, len)) => {
+                if serde_json::from_value::<T>(value.clone()).is_ok() {
+                    ret.insert(key, value);
+                }
+
+                pos += len;
+            },
+            None => {
+                // Skip to the start of the next entry
+                let skip = rest.char_indices().nth(1)
+                    .map_or(rest.len(), |(i, _)| i);
+
+                match rest[skip..].find(",\"") {
+                    Some(p) => pos += skip + p + 1,
+                    None => break,
+                }
+            },
+        }
+    }
+
+    return ret;
+}
+
+// Position after the opening brace of the named object. Only keys of the
+// top-level object are matched, so that a word or key with the name of a
+// section is not taken for the section.
+fn section_start(data: &str, name: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut pos = 0;
+
+    while let Some(c) = data[pos..].chars().next() {
+        if c != '"' {
+            match c {
+                '{' | '[' => depth += 1,
+                '}' | ']' => depth -= 1,
+                _ => { },
+            }
+
+            pos += c.len_utf8();
+            continue;
+        }
+
+        // Skip the string, including escaped quotes
+        let rest = &data[pos..];
+        let mut escaped = false;
+        let mut opt_end = None;
+
+        for (i, d) in rest.char_indices().skip(1) {
+            if escaped {
+                escaped = false;
+            }
+            else if d == '\\' {
+                escaped = true;
+            }
+            else if d == '"' {
+                opt_end = Some(i);
+                break;
+            }
+        }
+
+        let end = opt_end?;
+        pos += end + 1;
+
+        if depth != 1 || &rest[1..end] != name {
+            continue;
+        }
+
+        let value = data[pos..].trim_start();
+
+        if let Some(v) = value.strip_prefix(':') {
+            let v = v.trim_start();
+
+            if v.starts_with('{') {
+                return Some(data.len() - v.len() + 1);
+            }
+        }
+    }
+
+    return None;
+}
+
+// Read a '"key": value' entry with an optional leading comma. Returns the
+// key, the value and the length of the entry.
+fn read_entry(data: &str) -> Option<(String, Value, usize)> {
+    let rest = data.trim_start();
+    let rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    let mut offset = data.len() - rest.len();
+
+    let mut stream = Deserializer::from_str(rest).into_iter::<String>();
+    let key = stream.next()?.ok()?;
+    offset += stream.byte_offset();
+
+    let rest = data[offset..].trim_start().strip_prefix(':')?;
+    offset = data.len() - rest.len();
+
+    let mut stream = Deserializer::from_str(rest).into_iter::<Value>();
+    let value = stream.next()?.ok()?;
+    offset += stream.byte_offset();
+
+    return Some((key, value, offset));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    type Words = HashMap<usize, Vec<String>>;
+
+    #[test]
+    fn recovers_entries_of_truncated_file() {
+        let data = r#"{"words":{"elv":{"3":["nil"]},"oss":{"2":["vi"]},"fl"#;
+        let words = recover_json_section::<Words>(data, "words");
+
+        assert_eq!(words.len(), 2);
+        assert!(words.contains_key("elv"));
+        assert!(words.contains_key("oss"));
+    }
+
+    #[test]
+    fn skips_damaged_entries() {
+        let data = concat!(r#"{"words":{"elv":{"3":["nil"]}ø,"#,
+                           r#""æøå":{"3":x},"oss":{"2":["vi"]}}}"#);
+        let words = recover_json_section::<Words>(data, "words");
+
+        assert_eq!(words.len(), 2);
+        assert!(words.contains_key("elv"));
+        assert!(words.contains_key("oss"));
+    }
+
+    #[test]
+    fn skips_entries_of_other_types() {
+        let data = r#"{"display":{"giseg":"gi seg","x":3}}"#;
+        let display = recover_json_section::<String>(data, "display");
+
+        assert_eq!(display.len(), 1);
+        assert_eq!(display["giseg"], "gi seg");
+    }
+
+    #[test]
+    fn matches_top_level_sections_only() {
+        let data = r#"{"choices":{"words":{"x":1}},"words":{"elv":{}}}"#;
+        let words = recover_json_section::<Words>(data, "words");
+
+        assert_eq!(words.len(), 1);
+        assert!(words.contains_key("elv"));
+    }
+
+    #[test]
+    fn missing_section_is_empty() {
+        let data = r#"{"words":{"elv":{}}}"#;
+
+        assert!(recover_json_section::<String>(data, "display").is_empty());
+    }
+}
@@ -0,0 +1,43 @@
+// Candidate scores. Each candidate is scored by where it was found in the
+// web lookup, how common the word is, how many of its letters agree with
+// the crossing words, and how often it has been chosen for the key before.
+
+// Weights of the score components
+const RANK_WEIGHT: f64 = 2.0;
+const FREQUENCY_WEIGHT: f64 = 1.0;
+const CROSSING_WEIGHT: f64 = 3.0;
+const CHOICE_WEIGHT: f64 = 10.0;
+const DERIVED_PENALTY: f64 = 5.0;
+
+#[derive(Clone, Default)]
+pub struct Score {
+    // Position in the dictionary list for the key, i.e. the web result
+    // order. None if the word is not listed for the key.
+    pub rank: Option<usize>,
+    // Occurrences of the word in the imported frequency list
+    pub frequency: u64,
+    // Number of crossing words which agree with the candidate. A crossing
+    // word agrees if it is placed with the same letter, or has a candidate
+    // with the same letter at the crossing.
+    pub crossings: usize,
+    // Number of times the word has been placed for the key before
+    pub choices: u32,
+    // The word is not listed for the key, but derived from other entries
+    // in the dictionary, and is less likely to be right
+    pub derived: bool,
+}
+
+impl Score {
+    pub fn total(&self) -> f64 {
+        let rank = match self.rank {
+            Some(r) => RANK_WEIGHT/(1.0 + r as f64),
+            None => 0.0,
+        };
+
+        return rank
+            + FREQUENCY_WEIGHT*(1.0 + self.frequency as f64).ln()
+            + CROSSING_WEIGHT*self.crossings as f64
+            + CHOICE_WEIGHT*self.choices as f64
+            - if self.derived { DERIVED_PENALTY } else { 0.0 };
+    }
+}
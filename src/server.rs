@@ -0,0 +1,334 @@
+use crate::board::Board;
+use crate::dictionary::{Dictionary, Source};
+use crate::json::{word_json, crossings_json, words_json, board_json};
+use crate::json::ranked_candidates_json;
+
+use crate::event::{BoardEvent, SharedObserver, notify};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response};
+use url::Url;
+
+// Error responses carry a http status code and a message
+type HttpResult = Result<Value, (u16, String)>;
+
+// Http server exposing puzzles and the dictionary as a json api. Each
+// loaded puzzle is a session, identified by name. Sessions are held in
+// memory and stored as json sessions in the session directory after each
+// change, so that they survive a restart of the server. Puzzles are only
+// loaded from the puzzle directory.
+pub struct Server {
+    dict: Dictionary,
+    sessions: HashMap<String, Board>,
+    dir: String,
+    puzzles: String,
+    observer: SharedObserver,
+    // Number of backups kept on explicit saves
+    backups: usize,
+}
+
+impl Server {
+    pub fn new(dict: Dictionary, dir: &str, puzzles: &str) -> Self {
+        Self {
+            backups: dict.backups,
+            dict: dict,
+            sessions: HashMap::new(),
+            dir: dir.to_string(),
+            puzzles: puzzles.to_string(),
+            observer: None,
+        }
+    }
+
+    pub fn set_observer(&mut self, observer: SharedObserver) {
+        self.observer = observer;
+    }
+
+    pub fn run(&mut self, address: &str) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Unable to create {}: {}", self.dir, e))?;
+
+        // Changes are stored without backups, so the dictionary is backed
+        // up once at startup
+        self.dict.back_up()?;
+
+        let server = tiny_http::Server::http(address)
+            .map_err(|e| format!("Unable to listen on {}: {}", address, e))?;
+
+        notify(&self.observer, BoardEvent::Listening(address.to_string()));
+
+        for request in server.incoming_requests() {
+            self.respond(request);
+        }
+
+        return Ok(());
+    }
+
+    fn respond(&mut self, mut request: Request) {
+        let (status, value) = match self.response(&mut request) {
+            Ok(v) => (200, v),
+            Err((status, msg)) => (status, json!({ "error": msg })),
+        };
+
+        let header = Header::from_bytes(&b"Content-Type"[..],
+                                        &b"application/json"[..]).unwrap();
+        let response = Response::from_string(value.to_string())
+            .with_status_code(status)
+            .with_header(header);
+
+        if let Err(e) = request.respond(response) {
+            notify(&self.observer, BoardEvent::ResponseFailed(e.to_string()));
+        }
+    }
+
+    // Parse the request and handle it. The request target is sent by the
+    // client, so an invalid one is an error response.
+    fn response(&mut self, request: &mut Request) -> HttpResult {
+        let url = Url::parse("http://localhost")
+            .and_then(|u| u.join(request.url()))
+            .map_err(|e| (400, format!("Invalid url {}: {}",
+                                       request.url(), e)))?;
+        let path: Vec<String> = url.path_segments()
+            .map(|s| s.filter(|p| !p.is_empty()).map(|p| p.to_string())
+                 .collect())
+            .unwrap_or_default();
+        let query: HashMap<String, String> = url.query_pairs()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        let mut data = String::new();
+        let body = match request.as_reader().read_to_string(&mut data) {
+            Ok(_) if data.trim().is_empty() => Ok(json!({})),
+            Ok(_) => serde_json::from_str(&data)
+                .map_err(|e| (400, format!("Invalid json: {}", e))),
+            Err(e) => Err((400, format!("Unable to read request: {}", e))),
+        };
+
+        let path: Vec<&str> = path.iter().map(|p| p.as_str()).collect();
+        let method = request.method().clone();
+
+        return self.handle(&method, &path, &query, &body?);
+    }
+
+    fn handle(&mut self, method: &Method, path: &[&str],
+              query: &HashMap<String, String>, body: &Value) -> HttpResult {
+        match (method, path) {
+            (Method::Get, ["puzzles"]) => {
+                let mut ids: Vec<&String> = self.sessions.keys().collect();
+                ids.sort();
+                Ok(json!(ids))
+            },
+            (Method::Post, ["puzzles"]) => {
+                self.load(body)
+            },
+            (Method::Get, ["puzzles", id]) => {
+                Ok(board_json(self.board(id)?))
+            },
+            (Method::Delete, ["puzzles", id]) => {
+                self.save(id, self.backups)?;
+                self.sessions.remove(*id);
+                Ok(json!({}))
+            },
+            (Method::Post, ["puzzles", id, "solve"]) => {
+                let board = self.sessions.get_mut(*id)
+                    .ok_or((404, format!("No puzzle {}", id)))?;
+                board.solve_repeated(&mut self.dict);
+                self.save(id, 0)?;
+                Ok(board_json(self.board(id)?))
+            },
+            (Method::Post, ["puzzles", id, "save"]) => {
+                self.save(id, self.backups)?;
+                Ok(board_json(self.board(id)?))
+            },
+            (Method::Get, ["puzzles", id, "words"]) => {
+                Ok(words_json(self.board(id)?))
+            },
+            (Method::Get, ["puzzles", id, "words", ix]) => {
+                let (board, a) = self.word(id, ix)?;
+                let mut value = word_json(board, a);
+                value["crossings"] = crossings_json(board, a);
+                Ok(value)
+            },
+            (Method::Get, ["puzzles", id, "words", ix, "candidates"]) => {
+                let (board, a) = self.word(id, ix)?;
+                Ok(ranked_candidates_json(board, a, &self.dict))
+            },
+            (Method::Post, ["puzzles", id, "words", ix, "place"]) => {
+                let word = body["word"].as_str()
+                    .ok_or((400, "Missing word".to_string()))?;
+                self.place(id, ix, word)
+            },
+            (Method::Post, ["puzzles", id, "words", ix, "unplace"]) => {
+                let (_, a) = self.word(id, ix)?;
+                let board = self.sessions.get_mut(*id).unwrap();
+                board.unplace(a, &mut self.dict);
+                self.save(id, 0)?;
+                Ok(words_json(self.board(id)?))
+            },
+            (Method::Get, ["lookup"]) => {
+                self.lookup(query)
+            },
+            (Method::Post, ["dictionary", "save"]) => {
+                self.write_dictionary(self.backups)?;
+                Ok(json!({ "filename": self.dict.filename }))
+            },
+            _ => {
+                Err((404, "Not found".to_string()))
+            }
+        }
+    }
+
+    fn board(&self, id: &str) -> Result<&Board, (u16, String)> {
+        self.sessions.get(id).ok_or((404, format!("No puzzle {}", id)))
+    }
+
+    fn word(&self, id: &str, ix: &str)
+            -> Result<(&Board, usize), (u16, String)> {
+        let board = self.board(id)?;
+        let a = ix.parse::<usize>()
+            .map_err(|_| (400, format!("Invalid word index {}", ix)))?;
+
+        if a >= board.words.len() {
+            return Err((404, format!("No word {}", a)));
+        }
+
+        return Ok((board, a));
+    }
+
+    fn session_file(&self, id: &str) -> String {
+        Path::new(&self.dir).join(format!("{}.json", id))
+            .to_string_lossy().to_string()
+    }
+
+    // Path of a puzzle file given relative to the puzzle directory. Files
+    // outside the directory are refused.
+    fn puzzle_file(&self, file: &str) -> Result<PathBuf, (u16, String)> {
+        let dir = fs::canonicalize(&self.puzzles)
+            .map_err(|e| (500, format!("Unable to read {}: {}",
+                                       self.puzzles, e)))?;
+        let path = fs::canonicalize(dir.join(file))
+            .map_err(|_| (404, format!("No such file {}", file)))?;
+
+        if !path.starts_with(&dir) || !path.is_file() {
+            return Err((403, format!("Not a puzzle file {}", file)));
+        }
+
+        return Ok(path);
+    }
+
+    // Load a puzzle into a new session. A stored session with the same
+    // name takes precedence over the given puzzle file.
+    fn load(&mut self, body: &Value) -> HttpResult {
+        let file = body["file"].as_str()
+            .ok_or((400, "Missing file".to_string()))?;
+        let id = match body["name"].as_str() {
+            Some(name) => name.to_string(),
+            None => Path::new(file).file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .ok_or((400, format!("Invalid file {}", file)))?,
+        };
+
+        // The name is used for the session file name
+        if id.is_empty() || id.starts_with('.') || id.contains('/') ||
+            id.contains('\\') {
+                return Err((400, format!("Invalid name {}", id)));
+            }
+
+        if !self.sessions.contains_key(&id) {
+            let sname = self.session_file(&id);
+            let board;
+
+            if Path::new(&sname).exists() {
+                board = Board::from_session_file(&sname, &mut self.dict)
+                    .map_err(|e| (500, e))?;
+            }
+            else {
+                let path = self.puzzle_file(file)?;
+                board = Board::from_file(&path.to_string_lossy(),
+                                         &mut self.dict)
+                    .map_err(|e| (400, e))?;
+            }
+
+            self.sessions.insert(id.clone(), board);
+        }
+
+        let mut value = board_json(self.board(&id)?);
+        value["id"] = json!(id);
+        return Ok(value);
+    }
+
+    fn place(&mut self, id: &str, ix: &str, word: &str) -> HttpResult {
+        let (board, a) = self.word(id, ix)?;
+        let grid = Dictionary::normalize(word);
+
+        if board.words[a].length != grid.chars().count() {
+            return Err((400, "Invalid length".to_string()));
+        }
+
+        if !self.dict.in_alphabet(&grid) {
+            return Err((400, "Invalid letters".to_string()));
+        }
+
+        let board = self.sessions.get_mut(id).unwrap();
+        board.place(a, Some(grid.clone()), &mut self.dict);
+
+        // Add word to dictionary if missing
+        if let Some(k) = &board.words[a].key {
+            self.dict.add_word(&k, word, Source::Placed);
+            self.dict.record_choice(&k, &grid);
+        }
+
+        self.save(id, 0)?;
+        return Ok(words_json(self.board(id)?));
+    }
+
+    fn lookup(&mut self, query: &HashMap<String, String>) -> HttpResult {
+        let key = query.get("key")
+            .ok_or((400, "Missing key".to_string()))?;
+        let opt_hint = query.get("hint").map(|h| h.as_str());
+        let length = match (query.get("length"), opt_hint) {
+            (Some(l), _) => l.parse::<usize>()
+                .map_err(|_| (400, format!("Invalid length {}", l)))?,
+            (None, Some(h)) => Dictionary::normalize(h).chars().count(),
+            (None, None) => return Err((400, "Missing length".to_string())),
+        };
+
+        let words = self.dict.lookup(key, length, opt_hint);
+
+        return Ok(json!({
+            "key": key,
+            "length": length,
+            "candidates": words,
+            "display": words.iter()
+                .map(|w| self.dict.display_form(key, w))
+                .collect::<Vec<String>>(),
+        }));
+    }
+
+    // Store the session board in the session directory, and the dictionary
+    // if it has changed. Saves after each change are made without backups,
+    // since they would soon replace all backups with recent copies.
+    fn save(&mut self, id: &str, backups: usize)
+            -> Result<(), (u16, String)> {
+        let fname = self.session_file(id);
+        let board = self.sessions.get_mut(id)
+            .ok_or((404, format!("No puzzle {}", id)))?;
+
+        board.backups = backups;
+        board.write_session_file(Some(&fname)).map_err(|e| (500, e))?;
+
+        if self.dict.changed {
+            self.write_dictionary(backups)?;
+        }
+
+        return Ok(());
+    }
+
+    fn write_dictionary(&mut self, backups: usize)
+                        -> Result<(), (u16, String)> {
+        self.dict.backups = backups;
+        self.dict.write_to_file(None).map_err(|e| (500, e))
+    }
+}
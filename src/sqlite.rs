@@ -0,0 +1,475 @@
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use std::collections::HashMap;
+use crate::dictionary::{Change, Provenance, Source};
+use std::time::Duration;
+
+// Dictionary storage in an sqlite database. Each key and each word is
+// stored in separate rows, so that changed entries can be written without
+// rewriting the whole dictionary. Several kryss instances may use the same
+// database, since writes are done in transactions. Keys are read when
+// they are needed, and only changed rows are written.
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS keys (
+        key TEXT PRIMARY KEY
+    );
+    CREATE TABLE IF NOT EXISTS words (
+        key TEXT NOT NULL,
+        length INTEGER NOT NULL,
+        rank INTEGER NOT NULL,
+        word TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS words_key ON words (key, length);
+    CREATE INDEX IF NOT EXISTS words_word ON words (word);
+    CREATE TABLE IF NOT EXISTS aliases (
+        key TEXT PRIMARY KEY,
+        target TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS choices (
+        key TEXT NOT NULL,
+        word TEXT NOT NULL,
+        count INTEGER NOT NULL,
+        PRIMARY KEY (key, word)
+    );
+    CREATE TABLE IF NOT EXISTS provenance (
+        key TEXT NOT NULL,
+        word TEXT NOT NULL,
+        source TEXT NOT NULL,
+        added INTEGER NOT NULL,
+        PRIMARY KEY (key, word)
+    );
+    CREATE TABLE IF NOT EXISTS display_forms (
+        key TEXT NOT NULL,
+        word TEXT NOT NULL,
+        form TEXT NOT NULL,
+        PRIMARY KEY (key, word)
+    );
+    CREATE TABLE IF NOT EXISTS frequencies (
+        word TEXT PRIMARY KEY,
+        count INTEGER NOT NULL
+    );
+";
+
+// Version of the schema, stored as the user_version of the database.
+// Databases of older versions are upgraded by creating the missing tables,
+// and by the upgrades below.
+const SCHEMA_VERSION: u32 = 2;
+
+// Version 1 stores the display forms by word. They are stored for each key
+// which lists the word.
+const UPGRADE_V1: &str = "
+    BEGIN;
+    CREATE TABLE IF NOT EXISTS display (
+        word TEXT PRIMARY KEY,
+        form TEXT NOT NULL
+    );
+    INSERT OR IGNORE INTO display_forms (key, word, form)
+        SELECT DISTINCT w.key, d.word, d.form
+        FROM display d JOIN words w ON w.word = d.word;
+    DROP TABLE display;
+    COMMIT;
+";
+
+// Time to wait for another instance holding the database lock
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub type Words = HashMap<usize, Vec<String>>;
+
+// Entries of a key
+#[derive(Default)]
+pub struct StoredKey {
+    pub words: Words,
+    pub choices: HashMap<String, u32>,
+    pub provenance: HashMap<String, Provenance>,
+    pub display: HashMap<String, String>,
+}
+
+#[derive(Default)]
+pub struct StoredEntries {
+    pub words: HashMap<String, Words>,
+    pub aliases: HashMap<String, String>,
+    pub choices: HashMap<String, HashMap<String, u32>>,
+    pub provenance: HashMap<String, HashMap<String, Provenance>>,
+    pub display: HashMap<String, HashMap<String, String>>,
+    pub frequencies: HashMap<String, u64>,
+}
+
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+fn db_error(e: rusqlite::Error) -> String {
+    format!("Database error: {}", e)
+}
+
+impl SqliteStore {
+    pub fn open(fname: &str) -> Result<Self, String> {
+        let conn = Connection::open(fname)
+            .map_err(|e| format!("Unable to open {}: {}", fname, e))?;
+
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(db_error)?;
+        // Readers are not blocked by a writer in wal mode
+        conn.pragma_update(None, "journal_mode", "wal").map_err(db_error)?;
+
+        let version: u32 = conn.query_row("PRAGMA user_version", [],
+                                          |r| r.get(0))
+            .map_err(db_error)?;
+
+        if version > SCHEMA_VERSION {
+            return Err(format!("{} has schema version {}, which is newer \
+                                than the supported version {}. Upgrade \
+                                kryss to read it.", fname, version,
+                               SCHEMA_VERSION));
+        }
+
+        conn.execute_batch(SCHEMA).map_err(db_error)?;
+
+        if version < 2 {
+            conn.execute_batch(UPGRADE_V1).map_err(db_error)?;
+        }
+
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)
+            .map_err(db_error)?;
+
+        Ok(Self { conn: conn })
+    }
+
+    // Read all entries, for commands which use the whole dictionary
+    pub fn load(&self) -> Result<StoredEntries, String> {
+        let mut ret = StoredEntries::default();
+
+        let mut stmt = self.conn.prepare("SELECT key FROM keys")
+            .map_err(db_error)?;
+        let rows = stmt.query_map([], |r| r.get::<_, String>(0))
+            .map_err(db_error)?;
+
+        for key in rows {
+            ret.words.insert(key.map_err(db_error)?, HashMap::new());
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT key, length, word FROM words ORDER BY key, length, rank")
+            .map_err(db_error)?;
+        let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?,
+                                               r.get::<_, i64>(1)? as usize,
+                                               r.get::<_, String>(2)?)))
+            .map_err(db_error)?;
+
+        for row in rows {
+            let (key, length, word) = row.map_err(db_error)?;
+            ret.words.entry(key).or_default().entry(length).or_default()
+                .push(word);
+        }
+
+        ret.aliases = self.load_pairs("SELECT key, target FROM aliases")?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT key, word, form FROM display_forms").map_err(db_error)?;
+        let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?,
+                                               r.get::<_, String>(1)?,
+                                               r.get::<_, String>(2)?)))
+            .map_err(db_error)?;
+
+        for row in rows {
+            let (key, word, form) = row.map_err(db_error)?;
+            ret.display.entry(key).or_default().insert(word, form);
+        }
+
+        let mut stmt = self.conn.prepare("SELECT word, count FROM frequencies")
+            .map_err(db_error)?;
+        let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?,
+                                               r.get::<_, i64>(1)? as u64)))
+            .map_err(db_error)?;
+
+        for row in rows {
+            let (word, count) = row.map_err(db_error)?;
+            ret.frequencies.insert(word, count);
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT key, word, count FROM choices").map_err(db_error)?;
+        let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?,
+                                               r.get::<_, String>(1)?,
+                                               r.get::<_, u32>(2)?)))
+            .map_err(db_error)?;
+
+        for row in rows {
+            let (key, word, count) = row.map_err(db_error)?;
+            ret.choices.entry(key).or_default().insert(word, count);
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT key, word, source, added FROM provenance")
+            .map_err(db_error)?;
+        let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?,
+                                               r.get::<_, String>(1)?,
+                                               r.get::<_, String>(2)?,
+                                               r.get::<_, i64>(3)? as u64)))
+            .map_err(db_error)?;
+
+        for row in rows {
+            let (key, word, source, added) = row.map_err(db_error)?;
+
+            if let Ok(source) = source.parse::<Source>() {
+                let prov = Provenance { source: source, added: added };
+                ret.provenance.entry(key).or_default().insert(word, prov);
+            }
+        }
+
+        return Ok(ret);
+    }
+
+    fn load_pairs(&self, sql: &str)
+                  -> Result<HashMap<String, String>, String> {
+        let mut stmt = self.conn.prepare(sql).map_err(db_error)?;
+        let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?,
+                                               r.get::<_, String>(1)?)))
+            .map_err(db_error)?;
+        let mut ret = HashMap::new();
+
+        for row in rows {
+            let (a, b) = row.map_err(db_error)?;
+            ret.insert(a, b);
+        }
+
+        return Ok(ret);
+    }
+
+    pub fn load_aliases(&self) -> Result<HashMap<String, String>, String> {
+        self.load_pairs("SELECT key, target FROM aliases")
+    }
+
+    // Entries of a key, if the key is stored. Keys are read when they are
+    // needed, rather than reading the whole database.
+    pub fn load_key(&self, key: &str) -> Result<Option<StoredKey>, String> {
+        let found = self.conn.query_row(
+            "SELECT key FROM keys WHERE key = ?1", params![key],
+            |r| r.get::<_, String>(0))
+            .optional().map_err(db_error)?;
+
+        if found.is_none() {
+            return Ok(None);
+        }
+
+        let mut ret = StoredKey::default();
+
+        let mut stmt = self.conn.prepare(
+            "SELECT length, word FROM words WHERE key = ?1
+             ORDER BY length, rank").map_err(db_error)?;
+        let rows = stmt.query_map(params![key],
+                                  |r| Ok((r.get::<_, i64>(0)? as usize,
+                                          r.get::<_, String>(1)?)))
+            .map_err(db_error)?;
+
+        for row in rows {
+            let (length, word) = row.map_err(db_error)?;
+            ret.words.entry(length).or_default().push(word);
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT word, count FROM choices WHERE key = ?1")
+            .map_err(db_error)?;
+        let rows = stmt.query_map(params![key],
+                                  |r| Ok((r.get::<_, String>(0)?,
+                                          r.get::<_, u32>(1)?)))
+            .map_err(db_error)?;
+
+        for row in rows {
+            let (word, count) = row.map_err(db_error)?;
+            ret.choices.insert(word, count);
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT word, source, added FROM provenance WHERE key = ?1")
+            .map_err(db_error)?;
+        let rows = stmt.query_map(params![key],
+                                  |r| Ok((r.get::<_, String>(0)?,
+                                          r.get::<_, String>(1)?,
+                                          r.get::<_, i64>(2)? as u64)))
+            .map_err(db_error)?;
+
+        for row in rows {
+            let (word, source, added) = row.map_err(db_error)?;
+
+            if let Ok(source) = source.parse::<Source>() {
+                let prov = Provenance { source: source, added: added };
+                ret.provenance.insert(word, prov);
+            }
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT word, form FROM display_forms WHERE key = ?1")
+            .map_err(db_error)?;
+        let rows = stmt.query_map(params![key],
+                                  |r| Ok((r.get::<_, String>(0)?,
+                                          r.get::<_, String>(1)?)))
+            .map_err(db_error)?;
+
+        for row in rows {
+            let (word, form) = row.map_err(db_error)?;
+            ret.display.insert(word, form);
+        }
+
+        return Ok(Some(ret));
+    }
+
+    // Display form of a word for the key, or for the first key with one
+    pub fn display(&self, opt_key: Option<&str>, word: &str)
+                   -> Result<Option<String>, String> {
+        let ret = match opt_key {
+            Some(key) => self.conn.query_row(
+                "SELECT form FROM display_forms WHERE key = ?1 AND word = ?2",
+                params![key, word], |r| r.get::<_, String>(0)),
+            None => self.conn.query_row(
+                "SELECT form FROM display_forms WHERE word = ?1
+                 ORDER BY key LIMIT 1",
+                params![word], |r| r.get::<_, String>(0)),
+        };
+
+        ret.optional().map_err(db_error)
+    }
+
+    pub fn frequency(&self, word: &str) -> Result<Option<u64>, String> {
+        self.conn.query_row("SELECT count FROM frequencies WHERE word = ?1",
+                            params![word], |r| r.get::<_, i64>(0))
+            .optional().map(|c| c.map(|c| c as u64)).map_err(db_error)
+    }
+
+    pub fn keys(&self) -> Result<Vec<String>, String> {
+        let mut ret = vec!();
+        self.for_each_row("SELECT key FROM keys", &mut |r| ret.push(r))?;
+
+        return Ok(ret);
+    }
+
+    // Keys which list the word
+    pub fn keys_of(&self, word: &str) -> Result<Vec<String>, String> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT key FROM words WHERE word = ?1")
+            .map_err(db_error)?;
+        let rows = stmt.query_map(params![word], |r| r.get::<_, String>(0))
+            .map_err(db_error)?;
+        let mut ret = vec!();
+
+        for row in rows {
+            ret.push(row.map_err(db_error)?);
+        }
+
+        return Ok(ret);
+    }
+
+    fn for_each_row(&self, sql: &str, f: &mut dyn FnMut(String))
+                    -> Result<(), String> {
+        let mut stmt = self.conn.prepare(sql).map_err(db_error)?;
+        let rows = stmt.query_map([], |r| r.get::<_, String>(0))
+            .map_err(db_error)?;
+
+        for row in rows {
+            f(row.map_err(db_error)?);
+        }
+
+        return Ok(());
+    }
+
+    // Apply changes to the stored entries in one transaction. Only the
+    // changed rows are written, so that entries stored by other instances
+    // since the dictionary was read are kept.
+    pub fn write(&mut self, changes: &[Change]) -> Result<(), String> {
+        let tx = self.conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_error)?;
+
+        for change in changes {
+            match change {
+                Change::AddKey(key) => {
+                    tx.execute("INSERT OR IGNORE INTO keys (key) VALUES (?1)",
+                               params![key]).map_err(db_error)?;
+                },
+                Change::AddWord { key, word } => {
+                    tx.execute("INSERT OR IGNORE INTO keys (key) VALUES (?1)",
+                               params![key]).map_err(db_error)?;
+
+                    let length = word.chars().count() as i64;
+                    let exists = tx.query_row(
+                        "SELECT rank FROM words
+                         WHERE key = ?1 AND length = ?2 AND word = ?3",
+                        params![key, length, word], |r| r.get::<_, i64>(0))
+                        .optional().map_err(db_error)?;
+
+                    if exists.is_none() {
+                        // New words are added after the known words
+                        tx.execute("INSERT INTO words (key, length, rank, word)
+                                    SELECT ?1, ?2,
+                                           COALESCE(MAX(rank) + 1, 0), ?3
+                                    FROM words
+                                    WHERE key = ?1 AND length = ?2",
+                                   params![key, length, word])
+                            .map_err(db_error)?;
+                    }
+                },
+                Change::RemoveWord { key, word } => {
+                    for table in ["words", "choices", "provenance",
+                                  "display_forms"] {
+                        tx.execute(&format!("DELETE FROM {} WHERE key = ?1
+                                             AND word = ?2", table),
+                                   params![key, word]).map_err(db_error)?;
+                    }
+                },
+                Change::RemoveKey(key) => {
+                    for table in ["keys", "words", "choices", "provenance",
+                                  "display_forms"] {
+                        tx.execute(&format!("DELETE FROM {} WHERE key = ?1",
+                                            table),
+                                   params![key]).map_err(db_error)?;
+                    }
+                },
+                Change::Choice { key, word, count } => {
+                    tx.execute("INSERT INTO choices (key, word, count)
+                                VALUES (?1, ?2, ?3)
+                                ON CONFLICT (key, word)
+                                DO UPDATE SET count = count + excluded.count",
+                               params![key, word, count])
+                        .map_err(db_error)?;
+                },
+                Change::Provenance { key, word, provenance: Some(p) } => {
+                    tx.execute("INSERT OR REPLACE INTO provenance
+                                (key, word, source, added)
+                                VALUES (?1, ?2, ?3, ?4)",
+                               params![key, word, p.source.as_str(),
+                                       p.added as i64])
+                        .map_err(db_error)?;
+                },
+                Change::Provenance { key, word, provenance: None } => {
+                    tx.execute("DELETE FROM provenance
+                                WHERE key = ?1 AND word = ?2",
+                               params![key, word]).map_err(db_error)?;
+                },
+                Change::Alias { key, target: Some(t) } => {
+                    tx.execute("INSERT OR REPLACE INTO aliases (key, target)
+                                VALUES (?1, ?2)", params![key, t])
+                        .map_err(db_error)?;
+                },
+                Change::Alias { key, target: None } => {
+                    tx.execute("DELETE FROM aliases WHERE key = ?1",
+                               params![key]).map_err(db_error)?;
+                },
+                Change::Display { key, word, form } => {
+                    tx.execute("INSERT OR REPLACE INTO display_forms
+                                (key, word, form) VALUES (?1, ?2, ?3)",
+                               params![key, word, form])
+                        .map_err(db_error)?;
+                },
+                Change::Frequency { word, count } => {
+                    tx.execute("INSERT OR REPLACE INTO frequencies
+                                (word, count) VALUES (?1, ?2)",
+                               params![word, *count as i64])
+                        .map_err(db_error)?;
+                },
+            }
+        }
+
+        tx.commit().map_err(db_error)?;
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,131 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use std::cmp::min;
+use std::iter::once;
+
+// Normalization of keys and words. Keys and words are compared in NFC
+// form and lower case, so that 'Ål', 'ål' and a decomposed 'å' are equal.
+
+// The Norwegian alphabet, with the accented letters of loan words such as
+// 'kafé' and 'à la carte'
+pub const DEFAULT_ALPHABET: &str = "abcdefghijklmnopqrstuvwxyzæøåàéèêóòôü";
+
+// Norwegian letters and their transliteration in English sources
+const TRANSLITERATIONS: &[(&str, &str)] = &[
+    ("æ", "ae"),
+    ("ø", "oe"),
+    ("å", "aa"),
+];
+
+pub fn normalize_key(key: &str) -> String {
+    key.nfc().collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+// Grid form of a word. Phrases are written without spaces and hyphens in
+// the grid.
+pub fn normalize_word(word: &str) -> String {
+    word.nfc()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+// Alternative spellings of a word with the Norwegian letters written as in
+// English sources, or the other way around.
+pub fn transliterations(word: &str) -> Vec<String> {
+    let mut folded = word.to_string();
+    let mut unfolded = word.to_string();
+
+    for (n, e) in TRANSLITERATIONS {
+        folded = folded.replace(e, n);
+        unfolded = unfolded.replace(n, e);
+    }
+
+    let mut ret = vec!();
+
+    for w in [folded, unfolded] {
+        if w != word && !ret.contains(&w) {
+            ret.push(w);
+        }
+    }
+
+    return ret;
+}
+
+// Key form used for fuzzy matching. Whitespace and diacritics are removed,
+// e.g. 'nyli vigd' becomes 'nylivigd' and 'café' becomes 'cafe'. The
+// Norwegian letters are kept, so that 'ål' is not folded to 'al'.
+pub fn fold_key(key: &str) -> String {
+    let mut ret = String::new();
+
+    for c in normalize_key(key).chars().filter(|c| !c.is_whitespace()) {
+        if "æøå".contains(c) {
+            ret.push(c);
+        }
+        else {
+            ret.extend(once(c).nfd().filter(|c| !is_combining_mark(*c)));
+        }
+    }
+
+    return ret;
+}
+
+// Levenshtein distance between two strings, counted in characters
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        let mut cur = vec![i; b.len() + 1];
+
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            cur[j] = min(min(prev[j] + 1, cur[j - 1] + 1),
+                         prev[j - 1] + cost);
+        }
+
+        prev = cur;
+    }
+
+    return prev[b.len()];
+}
+
+pub fn in_alphabet(word: &str, alphabet: &str) -> bool {
+    word.chars().all(|c| alphabet.contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fold_key_removes_whitespace_and_diacritics() {
+        assert_eq!(fold_key("Nyli  vigd"), "nylivigd");
+        assert_eq!(fold_key("café"), "cafe");
+        assert_eq!(fold_key("cafe\u{301}"), "cafe");
+        assert_eq!(fold_key("à la carte"), "alacarte");
+    }
+
+    #[test]
+    fn fold_key_keeps_norwegian_letters() {
+        assert_eq!(fold_key("Ål"), "ål");
+        assert_eq!(fold_key("a\u{30a}l"), "ål");
+        assert_eq!(fold_key("Ærø"), "ærø");
+    }
+
+    #[test]
+    fn edit_distance_counts_characters() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("nyligvigd", "nylivigd"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("ål", "al"), 1);
+        assert_eq!(edit_distance("blåbær", "blabaer"), 3);
+    }
+}
@@ -1,6 +1,8 @@
-use std::fmt::{Formatter, Result, Display};
+use std::fmt::{self, Formatter, Display};
+use serde::{Deserialize, Serialize};
+use crate::text::{normalize_key, normalize_word};
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Serialize, Deserialize)]
 pub enum Orientation {
     Right,
     Left,
@@ -37,7 +39,7 @@ impl Orientation {
 }
 
 impl Display for Orientation {
-    fn fmt(&self, f: &mut Formatter) -> Result {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "{}", match self {
             Orientation::Right => 'R',
             Orientation::Left  => 'L',
@@ -47,7 +49,7 @@ impl Display for Orientation {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Word {
     pub o: Orientation,
     pub x: usize,
@@ -56,60 +58,161 @@ pub struct Word {
     pub key: Option<String>,
     pub candidates: Vec<String>,
     pub placed: bool,
+    // Coordinates of the letters of a word made of individual cells, like
+    // the numbered cells of a solution. Empty for words in a line, which
+    // are given by orientation, position and length.
+    #[serde(default)]
+    pub cells: Vec<(usize, usize)>,
 }
 
 impl Word {
-    pub fn from_parts(parts: &[&str]) -> Self {
+    // Number of description file fields of the solution word starting at
+    // parts[0]
+    pub fn num_parts(parts: &[&str]) -> Result<usize, String> {
+        if parts[0] == "C" {
+            let len_part = parts.get(1)
+                .ok_or("Missing length of cells".to_string())?;
+            let (length, _) = parse_length(len_part)?;
+            return length.checked_mul(2).and_then(|n| n.checked_add(2))
+                .ok_or(format!("Invalid length {}", len_part));
+        }
+
+        return Ok(4);
+    }
+
+    // Word of a solution line, which may be made of individual cells
+    pub fn from_solution_parts(parts: &[&str]) -> Result<Self, String> {
+        if parts[0] == "C" {
+            return Self::from_cell_parts(parts);
+        }
+
+        return Self::from_parts(parts);
+    }
+
+    pub fn from_parts(parts: &[&str]) -> Result<Self, String> {
+        if parts.len() < 4 || parts.len() > 5 {
+            return Err(format!("Invalid word {}", parts.join(",")));
+        }
+
         let o = match parts[0] {
             "R" => Orientation::Right,
             "L" => Orientation::Left,
             "D" => Orientation::Down,
             "U" => Orientation::Up,
-            inv => panic!("Invalid orientation {}", inv)
+            inv => return Err(format!("Invalid orientation {}", inv)),
         };
 
-        let x = parts[1].parse().unwrap();
-        let y = parts[2].parse().unwrap();
+        let x = parse_number(parts[1])?;
+        let y = parse_number(parts[2])?;
         let len_part = parts[3];
         let (length, candidates, key);
 
         if parts.len() > 4 {
-            length = len_part.parse().unwrap();
+            length = parse_number(len_part)?;
             let key_part = parts[4].to_string();
 
             if let Some(i) = key_part.find('=') {
-                candidates = vec![key_part[i + 1..key_part.len()].to_string()];
-                key = Some(key_part[0..i].to_string());
+                candidates = vec![normalize_word(&key_part[i + 1..])];
+                key = Some(normalize_key(&key_part[0..i]));
             }
             else {
                 candidates = vec!();
-                key = Some(key_part);
+                key = Some(normalize_key(&key_part));
             }
         }
         else {
             key = None;
-
-            if let Some(i) = len_part.find('=') {
-                candidates = vec![len_part[i + 1..len_part.len()].to_string()];
-                length = len_part[0..i].to_string().parse().unwrap();
-            }
-            else {
-                candidates = vec!();
-                length = len_part.parse().unwrap();
-            }
+            (length, candidates) = parse_length(len_part)?;
         }
 
-        let placed = !candidates.is_empty();
-
-        Self {
+        let ret = Self {
             o: o,
             x: x,
             y: y,
             length: length,
             key: key,
+            placed: !candidates.is_empty(),
+            candidates: candidates,
+            cells: vec!(),
+        };
+
+        ret.check_geometry()?;
+        return Ok(ret);
+    }
+
+    // Word made of individual cells, on the form C,L[=word],X1,Y1,...,XL,YL
+    fn from_cell_parts(parts: &[&str]) -> Result<Self, String> {
+        let len_part = parts.get(1)
+            .ok_or("Missing length of cells".to_string())?;
+        let (length, candidates) = parse_length(len_part)?;
+
+        if length == 0 || parts.len() != Self::num_parts(parts)? {
+            return Err(format!("Invalid cells {}", parts.join(",")));
+        }
+
+        let mut cells = vec!();
+
+        for i in 0..length {
+            cells.push((parse_number(parts[2 + 2*i])?,
+                        parse_number(parts[3 + 2*i])?));
+        }
+
+        let ret = Self {
+            o: Orientation::Right,
+            x: cells[0].0,
+            y: cells[0].1,
+            length: length,
+            key: None,
+            placed: !candidates.is_empty(),
             candidates: candidates,
-            placed: placed,
+            cells: cells,
+        };
+
+        ret.check_geometry()?;
+        return Ok(ret);
+    }
+
+    // Check that the word has a length, fits in the grid and that a placed
+    // word has the right length
+    fn check_geometry(&self) -> Result<(), String> {
+        let too_long = match self.o {
+            Orientation::Left => self.length > self.x + 1,
+            Orientation::Up => self.length > self.y + 1,
+            _ => false,
+        };
+
+        if self.length == 0 || too_long {
+            return Err(format!("Invalid length of {}", self.to_string()));
         }
+
+        if self.placed && self.candidates[0].chars().count() != self.length {
+            return Err(format!("Word {} does not have {} letters",
+                               self.to_string(), self.length));
+        }
+
+        return Ok(());
+    }
+
+    pub fn is_cells(&self) -> bool {
+        return !self.cells.is_empty();
+    }
+
+    // Pairs of letter indexes in this and the other word which are in the
+    // same cell
+    pub fn shared_cells(&self, other: &Word) -> Vec<(usize, usize)> {
+        let mut ret = vec!();
+
+        for ai in 0..self.length {
+            let pos = self.position_at_index(ai);
+
+            for bi in 0..other.length {
+                if other.position_at_index(bi) == pos {
+                    ret.push((ai, bi));
+                }
+            }
+        }
+
+        return ret;
     }
 
     pub fn char_at(&self, ix: usize) -> char {
@@ -142,6 +245,11 @@ impl Word {
     }
 
     pub fn is_conflicting(&self, b: &Word) -> bool {
+        // Cells may be anywhere in the grid
+        if self.is_cells() || b.is_cells() {
+            return false;
+        }
+
         // Crossing words are not conflicting
         if self.is_crossing(b) {
             return false;
@@ -166,8 +274,8 @@ impl Word {
         }
 
         // Check sides
-        if b_xmax + 1 < a_xmin || b_xmin - 1 > a_xmax ||
-            b_ymax + 1 < a_ymin || b_ymin - 1 > a_ymax {
+        if b_xmax + 1 < a_xmin || b_xmin > a_xmax + 1 ||
+            b_ymax + 1 < a_ymin || b_ymin > a_ymax + 1 {
             return false;
         }
 
@@ -187,6 +295,10 @@ impl Word {
     }
 
     pub fn is_crossing(&self, word: &Word) -> bool {
+        if self.is_cells() || word.is_cells() {
+            return !self.shared_cells(word).is_empty();
+        }
+
         // Words in the same or oposite direction are not crossing
         if self.o.same_or_opposite_direction(&word.o) {
             return false;
@@ -209,6 +321,10 @@ impl Word {
     }
 
     pub fn xmin(&self) -> usize {
+        if self.is_cells() {
+            return self.cells.iter().map(|c| c.0).min().unwrap();
+        }
+
         match self.o {
             Orientation::Right | Orientation::Down | Orientation::Up => {
                 return self.x;
@@ -220,6 +336,10 @@ impl Word {
     }
 
     pub fn ymin(&self) -> usize {
+        if self.is_cells() {
+            return self.cells.iter().map(|c| c.1).min().unwrap();
+        }
+
         match self.o {
             Orientation::Right | Orientation::Left | Orientation::Down => {
                 return self.y;
@@ -231,6 +351,10 @@ impl Word {
     }
 
     pub fn xmax(&self) -> usize {
+        if self.is_cells() {
+            return self.cells.iter().map(|c| c.0).max().unwrap();
+        }
+
         match self.o {
             Orientation::Right => {
                 return self.x + self.length - 1;
@@ -242,6 +366,10 @@ impl Word {
     }
 
     pub fn ymax(&self) -> usize {
+        if self.is_cells() {
+            return self.cells.iter().map(|c| c.1).max().unwrap();
+        }
+
         match self.o {
             Orientation::Right | Orientation::Left | Orientation::Up => {
                 return self.y;
@@ -265,6 +393,10 @@ impl Word {
     }
 
     pub fn position_at_index(&self, i: usize) -> (usize, usize) {
+        if self.is_cells() {
+            return self.cells[i];
+        }
+
         return match self.o {
             Orientation::Right => (self.x + i, self.y),
             Orientation::Left  => (self.x - i, self.y),
@@ -274,6 +406,10 @@ impl Word {
     }
 
     pub fn position_in_word(&self, x: usize, y: usize) -> bool {
+        if self.is_cells() {
+            return self.cells.contains(&(x, y));
+        }
+
         return x >= self.xmin()
             && x <= self.xmax()
             && y >= self.ymin()
@@ -285,6 +421,19 @@ impl Word {
     }
 }
 
+fn parse_number(s: &str) -> Result<usize, String> {
+    s.trim().parse().map_err(|_| format!("Invalid number {}", s))
+}
+
+// Length field of a word without a key, with the placed word if any
+fn parse_length(len_part: &str) -> Result<(usize, Vec<String>), String> {
+    match len_part.find('=') {
+        Some(i) => Ok((parse_number(&len_part[0..i])?,
+                       vec![normalize_word(&len_part[i + 1..])])),
+        None => Ok((parse_number(len_part)?, vec!())),
+    }
+}
+
 impl<'a> IntoIterator for &'a Word {
     type Item = (usize, usize, char);
 
@@ -297,6 +446,20 @@ impl<'a> IntoIterator for &'a Word {
 
 impl ToString for Word {
     fn to_string(&self) -> String {
+        if self.is_cells() {
+            let cells: Vec<String> = self.cells.iter()
+                .map(|(x, y)| format!("{},{}", x, y))
+                .collect();
+
+            if self.placed {
+                return format!("C,{}={},{}", self.length,
+                               self.candidates[0], cells.join(","));
+            }
+            else {
+                return format!("C,{},{}", self.length, cells.join(","));
+            }
+        }
+
         if let Some(k) = &self.key {
             if self.placed {
                 return format!("{},{},{},{},{}={}", self.o, self.x, self.y,
@@ -359,3 +522,63 @@ impl<'a> Iterator for WordIter<'a> {
         return Some((x, y, c));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(line: &str) -> Result<Word, String> {
+        let parts: Vec<&str> = line.split(',').collect();
+        Word::from_solution_parts(&parts)
+    }
+
+    #[test]
+    fn num_parts_of_solution_words() {
+        assert_eq!(Word::num_parts(&["R", "0", "1", "5"]), Ok(4));
+        assert_eq!(Word::num_parts(&["C", "3", "0", "0"]), Ok(8));
+        assert_eq!(Word::num_parts(&["C", "2=ab"]), Ok(6));
+    }
+
+    #[test]
+    fn num_parts_of_invalid_cells() {
+        assert!(Word::num_parts(&["C"]).is_err());
+        assert!(Word::num_parts(&["C", "x"]).is_err());
+        assert!(Word::num_parts(&["C", "18446744073709551615"]).is_err());
+    }
+
+    #[test]
+    fn reads_cells() {
+        let w = cells("C,3,4,0,1,2,0,5").unwrap();
+
+        assert!(w.is_cells());
+        assert!(!w.placed);
+        assert_eq!(w.length, 3);
+        assert_eq!(w.cells, vec!((4, 0), (1, 2), (0, 5)));
+        assert_eq!(w.position_at_index(1), (1, 2));
+        assert_eq!(w.to_string(), "C,3,4,0,1,2,0,5");
+
+        let w = cells("C,2=Ål,0,0,1,1").unwrap();
+
+        assert!(w.placed);
+        assert_eq!(w.candidates, vec!("ål"));
+        assert_eq!(w.to_string(), "C,2=ål,0,0,1,1");
+    }
+
+    #[test]
+    fn refuses_invalid_cells() {
+        assert!(cells("C,3,0,0,1,0").is_err());
+        assert!(cells("C,2,0,0,1,0,2,0").is_err());
+        assert!(cells("C,0").is_err());
+        assert!(cells("C,2,0,x,1,0").is_err());
+        assert!(cells("C,2=abc,0,0,1,0").is_err());
+    }
+
+    #[test]
+    fn reads_solution_lines() {
+        let w = cells("R,1,2,3").unwrap();
+
+        assert!(!w.is_cells());
+        assert_eq!(w.key, None);
+        assert_eq!(w.position_at_index(2), (3, 2));
+    }
+}